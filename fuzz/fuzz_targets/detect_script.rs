@@ -0,0 +1,18 @@
+#![no_main]
+
+use bsv::Script;
+use libfuzzer_sys::fuzz_target;
+use magic_miner::MagicMiner;
+
+// arbitrary output scripts must never panic the puzzle detection
+fuzz_target!(|data: &[u8]| {
+    let script = match Script::from_bytes(data) {
+        Ok(script) => script,
+        Err(_) => return,
+    };
+
+    if let Ok(true) = MagicMiner::is_21e8_out(&script) {
+        let target = MagicMiner::puzzle_target(&script).expect("detected puzzle has a target");
+        assert!(target.starts_with(&[0x21, 0xe8]) && target.len() <= 32);
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use bsv::Transaction;
+use libfuzzer_sys::fuzz_target;
+use magic_miner::MagicMiner;
+
+// scanning any decodable transaction must never panic
+fuzz_target!(|data: &[u8]| {
+    let tx = match Transaction::from_bytes(data) {
+        Ok(tx) => tx,
+        Err(_) => return,
+    };
+
+    if let Ok(found) = MagicMiner::find_21e8_outputs(&tx) {
+        for (vout, _) in found {
+            assert!(MagicMiner::select_output(&tx, vout).is_ok());
+        }
+    }
+
+    for vout in 0..tx.get_noutputs() + 1 {
+        let _ = MagicMiner::select_output(&tx, vout);
+    }
+});
@@ -0,0 +1,176 @@
+use crate::Res;
+use bsv::Transaction;
+use std::path::{Path, PathBuf};
+
+/// Raw transactions stored on disk by txid
+pub struct TxCache {
+    dir: PathBuf,
+}
+
+impl Default for TxCache {
+    fn default() -> Self {
+        TxCache::new("cache")
+    }
+}
+
+impl TxCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        TxCache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn entry_path(&self, txid: &str) -> PathBuf {
+        self.dir.join(format!("{}.hex", txid.to_lowercase()))
+    }
+
+    /// Only returns transactions whose hash matches the requested txid
+    pub fn get(&self, txid: &str) -> Option<Transaction> {
+        let path = self.entry_path(txid);
+        let tx_hex = std::fs::read_to_string(&path).ok()?;
+
+        match Transaction::from_hex(tx_hex.trim()) {
+            Ok(tx) if tx.get_id_hex().ok()?.eq_ignore_ascii_case(txid) => Some(tx),
+            _ => {
+                let _ = std::fs::remove_file(path);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, tx: &Transaction) -> Res<String> {
+        if !self.dir.exists() {
+            std::fs::create_dir_all(&self.dir)?;
+        }
+
+        let txid = tx.get_id_hex()?;
+        let path = self.entry_path(&txid);
+
+        if path.exists() {
+            return Ok(txid);
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, tx.to_hex()?)?;
+        std::fs::rename(tmp_path, path)?;
+
+        Ok(txid)
+    }
+
+    /// Imports a raw hex file, or every file in a folder, returning the cached txids
+    pub fn seed_from_path<P: AsRef<Path>>(&self, path: P) -> Res<Vec<String>> {
+        let path = path.as_ref();
+
+        let files = match path.is_dir() {
+            true => std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect(),
+            false => vec![path.to_path_buf()],
+        };
+
+        let mut txids = Vec::with_capacity(files.len());
+
+        for file in files {
+            let tx_hex = std::fs::read_to_string(&file)?;
+
+            let tx = match Transaction::from_hex(tx_hex.trim()) {
+                Ok(tx) => tx,
+                Err(e) => {
+                    tracing::warn!(file = %file.display(), error = %e, "skipping invalid transaction");
+                    continue;
+                }
+            };
+
+            txids.push(self.put(&tx)?);
+        }
+
+        Ok(txids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsv::{Script, TxIn, TxOut};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("magic_miner_cache_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn test_tx(satoshis: u64) -> Transaction {
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&TxIn::new(&[1u8; 32], 0, &Script::default(), None));
+        tx.add_output(&TxOut::new(
+            satoshis,
+            &Script::from_asm_string("OP_FALSE OP_RETURN").unwrap(),
+        ));
+        tx
+    }
+
+    #[test]
+    fn put_then_get_round_trips() {
+        let dir = test_dir("round_trip");
+        let cache = TxCache::new(&dir);
+        let tx = test_tx(1000);
+
+        let txid = cache.put(&tx).unwrap();
+
+        assert_eq!(txid, tx.get_id_hex().unwrap());
+        assert_eq!(
+            cache.get(&txid.to_uppercase()).unwrap().to_hex().unwrap(),
+            tx.to_hex().unwrap()
+        );
+
+        // a second put keeps the existing entry
+        assert_eq!(cache.put(&tx).unwrap(), txid);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn missing_entries_are_none() {
+        let cache = TxCache::new(test_dir("missing"));
+        assert!(cache.get(&"00".repeat(32)).is_none());
+    }
+
+    #[test]
+    fn mismatched_entries_are_evicted() {
+        let dir = test_dir("mismatch");
+        let cache = TxCache::new(&dir);
+
+        let txid = cache.put(&test_tx(1000)).unwrap();
+        let path = cache.entry_path(&txid);
+
+        std::fs::write(&path, test_tx(2000).to_hex().unwrap()).unwrap();
+        assert!(cache.get(&txid).is_none());
+        assert!(!path.exists());
+
+        std::fs::write(&path, "not a transaction").unwrap();
+        assert!(cache.get(&txid).is_none());
+        assert!(!path.exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn seeds_valid_files_and_skips_invalid_ones() {
+        let dir = test_dir("seed");
+        let import = dir.join("import");
+        std::fs::create_dir_all(&import).unwrap();
+
+        let tx = test_tx(1000);
+        std::fs::write(import.join("a.txt"), format!("{}\n", tx.to_hex().unwrap())).unwrap();
+        std::fs::write(import.join("b.txt"), "zz").unwrap();
+
+        let cache = TxCache::new(dir.join("cache"));
+        let txids = cache.seed_from_path(&import).unwrap();
+
+        assert_eq!(txids, vec![tx.get_id_hex().unwrap()]);
+        assert!(cache.get(&txids[0]).is_some());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,8 +1,222 @@
-use crate::{MagicMiner, Res};
-use asky::{Select, SelectOption};
+use crate::fee::placeholder_p2pkh;
+use crate::output::{self, Event, OutputMode};
+use crate::{
+    logging, metrics, utils, Config, Content, Cpfp, Funding, MagicMiner, MinerError, Payout,
+    Prompt, Publisher, Res, SetupAnswers, TxCache, Wallet,
+};
+use asky::{Select, SelectOption, Text};
+use bsv::P2PKHAddress;
+use clap::{Parser, Subcommand};
+use std::process::ExitCode;
 
 pub struct CLI;
 
+pub const EXIT_OK: u8 = 0;
+pub const EXIT_ERROR: u8 = 1;
+pub const EXIT_INVALID_INPUT: u8 = 3;
+pub const EXIT_NO_PUZZLE: u8 = 4;
+pub const EXIT_CONFIG: u8 = 5;
+pub const EXIT_NETWORK: u8 = 6;
+pub const EXIT_INVALID_SOLUTION: u8 = 7;
+pub const EXIT_INSUFFICIENT_VALUE: u8 = 8;
+pub const EXIT_VERIFICATION: u8 = 9;
+
+#[derive(Parser)]
+#[command(name = "start", version, about = "21e8miner implemented in Rust")]
+pub struct Args {
+    /// Path to the miner config
+    #[arg(long, short, global = true, default_value = "Config.toml")]
+    pub config: String,
+
+    /// Print one JSON object per line instead of text
+    #[arg(long, global = true, conflicts_with = "tui")]
+    pub json: bool,
+
+    /// Show a full-screen dashboard while mining
+    #[arg(long, global = true)]
+    pub tui: bool,
+
+    /// Increase log verbosity (-v info, -vv debug, -vvv trace)
+    #[arg(long, short, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Also write logs to a daily rotating file in this folder
+    #[arg(long, global = true)]
+    pub log_dir: Option<String>,
+
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9184
+    #[arg(long, global = true)]
+    pub metrics: Option<String>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Mine a 21e8 output without prompts
+    Mine(MineArgs),
+    /// Configure Miner ID and default payout settings
+    Setup(SetupArgs),
+    /// List the 21e8 outputs of a transaction
+    Inspect {
+        /// Txid, outpoint or explorer URL
+        txid: String,
+    },
+    /// Check that a solved transaction is a valid proof of work
+    Verify {
+        /// Raw transaction hex, file path or - for stdin
+        tx: String,
+    },
+    /// Broadcast a raw transaction
+    Broadcast {
+        /// Raw transaction hex, file path or - for stdin
+        tx: String,
+    },
+    /// Broadcast every saved solution in a folder
+    Publish {
+        #[arg(long, default_value = "solved")]
+        dir: String,
+    },
+    /// Bump the fee of a saved solution by spending its payout in a child transaction
+    Bump(BumpArgs),
+    /// Publish a new 21e8 job funded from a WIF
+    Job(JobArgs),
+    /// Track and sweep the payouts of saved solutions
+    Wallet {
+        /// Folder of saved solutions
+        #[arg(long, default_value = "solved")]
+        dir: String,
+        #[command(subcommand)]
+        command: WalletCommand,
+    },
+}
+
+#[derive(Default, clap::Args)]
+pub struct SetupArgs {
+    /// Overwrite an existing config without asking
+    #[arg(long)]
+    pub force: bool,
+    #[command(flatten)]
+    pub answers: SetupAnswers,
+}
+
+/// Arguments of the standalone `setup` binary
+#[derive(Parser)]
+#[command(
+    name = "setup",
+    version,
+    about = "Configure Miner ID and default payout settings"
+)]
+pub struct SetupCommand {
+    /// Path to the miner config
+    #[arg(long, short, default_value = "Config.toml")]
+    pub config: String,
+
+    #[command(flatten)]
+    pub args: SetupArgs,
+}
+
+#[derive(clap::Args)]
+pub struct JobArgs {
+    /// SHA-256 hash to ask proof of work for, in hex
+    #[arg(long, required_unless_present_any = ["file", "text", "url"], conflicts_with_all = ["file", "text", "url"])]
+    pub hash: Option<String>,
+    /// Hash this file
+    #[arg(long, conflicts_with_all = ["text", "url"])]
+    pub file: Option<String>,
+    /// Hash this text
+    #[arg(long, conflicts_with = "url")]
+    pub text: Option<String>,
+    /// Download and hash this URL
+    #[arg(long)]
+    pub url: Option<String>,
+    /// Store the content on chain in a B:// output next to the job
+    #[arg(long, conflicts_with_all = ["hash", "reference"])]
+    pub embed: bool,
+    /// Store only this reference, e.g. the URL, in a B:// output next to the job
+    #[arg(long, conflicts_with = "embed")]
+    pub reference: Option<String>,
+    /// Hex target after the 21e8 prefix, longer is harder
+    #[arg(long, default_value = "")]
+    pub target: String,
+    /// Reward locked in the job
+    #[arg(long)]
+    pub satoshis: u64,
+    /// WIF or xprv to fund the job, defaults to funding_key
+    #[arg(long)]
+    pub key: Option<String>,
+    /// Satoshis per 1000 bytes, defaults to fee_rate
+    #[arg(long)]
+    pub fee_rate: Option<u64>,
+    /// Save the job to the published folder instead of broadcasting
+    #[arg(long)]
+    pub no_publish: bool,
+}
+
+#[derive(Subcommand)]
+pub enum WalletCommand {
+    /// Unspent payouts per address
+    Balance,
+    /// Every payout with its spent state
+    History,
+    /// Send every unspent payout we hold the key for to one destination
+    Sweep {
+        /// Destination address or script, defaults to pay_to
+        #[arg(long)]
+        to: Option<String>,
+        /// Satoshis per 1000 bytes, defaults to fee_rate
+        #[arg(long)]
+        fee_rate: Option<u64>,
+        /// Print the sweep transaction instead of broadcasting
+        #[arg(long)]
+        no_publish: bool,
+    },
+}
+
+#[derive(clap::Args)]
+pub struct BumpArgs {
+    /// Saved solution, e.g. solved/<txid>.txt
+    pub solution: String,
+    /// WIF or xprv of the payout, defaults to funding_key
+    #[arg(long)]
+    pub key: Option<String>,
+    /// Where the child sends the payout, defaults to the key's own address
+    #[arg(long)]
+    pub to: Option<String>,
+    /// Satoshis per 1000 bytes for parent and child together, defaults to fee_rate
+    #[arg(long)]
+    pub fee_rate: Option<u64>,
+    /// Print the child transaction instead of broadcasting
+    #[arg(long)]
+    pub no_publish: bool,
+}
+
+#[derive(clap::Args)]
+pub struct MineArgs {
+    /// Target txid, outpoint (txid:vout) or explorer URL
+    #[arg(long, conflicts_with = "raw")]
+    pub txid: Option<String>,
+    /// Raw transaction hex, file path or - for stdin
+    #[arg(long)]
+    pub raw: Option<String>,
+    /// Output index of the 21e8 script
+    #[arg(long)]
+    pub vout: Option<usize>,
+    /// P2PKH address, handle or locking script, overrides the config
+    #[arg(long)]
+    pub pay_to: Option<String>,
+    /// Number of mining threads (0 uses all available cores)
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Do not broadcast the solved transaction
+    #[arg(long)]
+    pub no_publish: bool,
+    /// Never touch the network, the signed transaction is saved to disk
+    #[arg(long)]
+    pub offline: bool,
+}
+
 const HEADER: &str =
     "┌┬┐┌─┐┌─┐┬┌─┐\n│││├─┤│ ┬││  \n┴ ┴┴ ┴└─┘┴└─┘\n┌┬┐┬┌┐┌┌─┐┬─┐\n│││││││├┤ ├┬┘\n┴ ┴┴┘└┘└─┘┴└─\n";
 
@@ -12,8 +226,12 @@ impl CLI {
 
         match Select::new_complex("⛏️ ", items).prompt() {
             Ok(action) => match action {
-                "Setup" => crate::config::init()?,
+                "Setup" => {
+                    CLI::setup(SetupArgs::default())?;
+                }
                 "Start" => MagicMiner::start().await?,
+                "Offline" => MagicMiner::start_offline().await?,
+                "Import" => CLI::import()?,
                 _ => (),
             },
             Err(e) => println!("{:?}", e),
@@ -21,8 +239,825 @@ impl CLI {
 
         Ok(())
     }
+
+    pub fn import() -> Res<()> {
+        let path = Text::new("Raw transaction hex file or folder").prompt()?;
+        let txids = TxCache::default().seed_from_path(path.trim())?;
+
+        for txid in &txids {
+            println!("Cached {}", txid);
+        }
+
+        Ok(())
+    }
+
+    /// Writes a new config from the given flags, prompting only for the missing values
+    pub fn setup(args: SetupArgs) -> Res<u8> {
+        if Config::existing_config() && !args.force {
+            let overwrite =
+                asky::Confirm::new("Found existing config. Would you like to overwrite it?")
+                    .prompt()?;
+
+            if !overwrite {
+                return Ok(EXIT_OK);
+            }
+        }
+
+        Prompt::run_setup_with(args.answers)?;
+
+        Ok(EXIT_OK)
+    }
+
+    pub async fn dispatch(command: Command) -> Res<u8> {
+        match command {
+            Command::Mine(args) => CLI::mine(args).await,
+            Command::Setup(args) => CLI::setup(args),
+            Command::Inspect { txid } => CLI::inspect(&txid).await,
+            Command::Verify { tx } => CLI::verify(&tx).await,
+            Command::Broadcast { tx } => {
+                let tx_hex = match utils::read_raw_tx(&tx) {
+                    Ok(tx_hex) => tx_hex,
+                    Err(e) => {
+                        output::emit(Event::Error {
+                            message: e.to_string(),
+                        });
+                        return Ok(EXIT_INVALID_INPUT);
+                    }
+                };
+
+                let result = MagicMiner::broadcast_tx(&tx_hex).await;
+
+                output::emit(Event::Broadcast {
+                    success: result.is_ok(),
+                    response: match &result {
+                        Ok(response) => response.trim().to_string(),
+                        Err(e) => e.to_string(),
+                    },
+                    path: None,
+                });
+
+                match result {
+                    Ok(_) => Ok(EXIT_OK),
+                    Err(_) => Ok(EXIT_NETWORK),
+                }
+            }
+            Command::Publish { dir } => CLI::publish(&dir).await,
+            Command::Bump(args) => CLI::bump(args).await,
+            Command::Job(args) => CLI::job(args).await,
+            Command::Wallet { dir, command } => CLI::wallet(&dir, command).await,
+        }
+    }
+
+    pub async fn job(args: JobArgs) -> Res<u8> {
+        let miner_config = match Config::existing_config() {
+            true => Config::read_from_toml()?,
+            false => Config::default(),
+        };
+
+        let target = match hex::decode(&args.target) {
+            Ok(target) => target,
+            Err(_) => return fail(String::from("--target must be hex"), EXIT_INVALID_INPUT),
+        };
+
+        let content = match (&args.file, &args.text, &args.url) {
+            (Some(file), _, _) => Some(Content::from_file(file)?),
+            (_, Some(text), _) => Some(Content::from_text(text)),
+            (_, _, Some(url)) => Some(Content::fetch(url).await?),
+            _ => None,
+        };
+
+        let hash = match (&content, &args.hash) {
+            (Some(content), _) => content.hash(),
+            (None, Some(hash)) => match hex::decode(hash) {
+                Ok(hash) => hash,
+                Err(_) => return fail(String::from("--hash must be hex"), EXIT_INVALID_INPUT),
+            },
+            (None, None) => {
+                return fail(
+                    String::from("One of --hash, --file, --text or --url is required"),
+                    EXIT_INVALID_INPUT,
+                )
+            }
+        };
+
+        output::emit(Event::ContentHash {
+            sha256: hex::encode(&hash),
+        });
+
+        let mut outputs = vec![Publisher::job_output(&hash, &target, args.satoshis)?];
+
+        // clap rejects --embed together with --reference
+        match (&content, &args.reference) {
+            (Some(content), None) if args.embed => outputs.push(content.b_output()?),
+            (_, Some(reference)) => outputs.push(Content::reference(reference).b_output()?),
+            _ => (),
+        }
+
+        let key = match args.key {
+            Some(key) => key,
+            None if !miner_config.funding_key.is_empty() => miner_config.funding_key.clone(),
+            None => {
+                return fail(
+                    String::from("No key to fund the job, use --key or set funding_key"),
+                    EXIT_CONFIG,
+                )
+            }
+        };
+
+        let funding = Funding::fetch(&key).await?;
+
+        let built = Publisher::build(
+            &funding,
+            &outputs,
+            args.fee_rate.unwrap_or(miner_config.fee_rate),
+        );
+
+        // nothing spends the UTXO, so other jobs may use it right away
+        let (tx, fees) = match built {
+            Ok(built) => built,
+            Err(e) => {
+                funding.release()?;
+                return Err(e);
+            }
+        };
+
+        let txid = tx.get_id_hex()?;
+        let tx_hex = tx.to_hex()?;
+
+        output::emit(Event::Built {
+            txid: txid.clone(),
+            size: fees.size,
+            fee: fees.fee,
+        });
+
+        if args.no_publish {
+            let path = utils::write_to_folder("published", &txid, &tx_hex)?;
+            output::emit(Event::Saved { path });
+
+            // keep a copy so the job can be mined before it is indexed
+            TxCache::default().put(&tx)?;
+            return Ok(EXIT_OK);
+        }
+
+        let result = MagicMiner::broadcast_tx(&tx_hex).await;
+
+        output::emit(Event::Broadcast {
+            success: result.is_ok(),
+            response: match &result {
+                Ok(response) => response.trim().to_string(),
+                Err(e) => e.to_string(),
+            },
+            path: None,
+        });
+
+        match result {
+            Ok(_) => {
+                TxCache::default().put(&tx)?;
+                Ok(EXIT_OK)
+            }
+            Err(_) => {
+                funding.release()?;
+                Ok(EXIT_NETWORK)
+            }
+        }
+    }
+
+    pub async fn wallet(dir: &str, command: WalletCommand) -> Res<u8> {
+        let miner_config = match Config::existing_config() {
+            true => Config::read_from_toml()?,
+            false => Config::default(),
+        };
+
+        let mut wallet = Wallet::load(dir)?;
+
+        if let Err(e) = wallet.refresh().await {
+            return fail(e.to_string(), EXIT_NETWORK);
+        }
+
+        match command {
+            WalletCommand::Balance => {
+                for (address, satoshis) in wallet.balances() {
+                    output::emit(Event::Balance { address, satoshis });
+                }
+            }
+            WalletCommand::History => {
+                for utxo in &wallet.utxos {
+                    output::emit(Event::WalletUtxo {
+                        txid: utxo.txid.clone(),
+                        vout: utxo.vout,
+                        address: utxo.address.clone(),
+                        satoshis: utxo.satoshis,
+                        spent: utxo.spent,
+                    });
+                }
+            }
+            WalletCommand::Sweep {
+                to,
+                fee_rate,
+                no_publish,
+            } => {
+                if miner_config.payout_key.is_empty() {
+                    return fail(String::from("No payout_key in the config"), EXIT_CONFIG);
+                }
+
+                let to = to.unwrap_or_else(|| miner_config.pay_to.clone());
+
+                if to.is_empty() {
+                    return fail(
+                        String::from("No destination, use --to or set pay_to"),
+                        EXIT_CONFIG,
+                    );
+                }
+
+                let fee_rate = fee_rate.unwrap_or(miner_config.fee_rate);
+
+                // size the sweep first, so a Paymail host is asked for the amount actually sent
+                let sized =
+                    wallet.sweep(&miner_config.payout_key, &placeholder_p2pkh()?, fee_rate)?;
+                let amount = sized.get_output(0).map_or(0, |o| o.get_satoshis());
+
+                let (_, script) =
+                    MagicMiner::resolve_address(&miner_config, &to, false, amount).await?;
+
+                let tx = wallet.sweep(&miner_config.payout_key, &script, fee_rate)?;
+                let tx_hex = tx.to_hex()?;
+
+                if no_publish {
+                    output::emit(Event::RawTx {
+                        txid: tx.get_id_hex()?,
+                        raw_tx: tx_hex,
+                    });
+                    return Ok(EXIT_OK);
+                }
+
+                let result = MagicMiner::broadcast_tx(&tx_hex).await;
+
+                output::emit(Event::Broadcast {
+                    success: result.is_ok(),
+                    response: match &result {
+                        Ok(response) => response.trim().to_string(),
+                        Err(e) => e.to_string(),
+                    },
+                    path: None,
+                });
+
+                if result.is_err() {
+                    return Ok(EXIT_NETWORK);
+                }
+            }
+        }
+
+        Ok(EXIT_OK)
+    }
+
+    pub async fn bump(args: BumpArgs) -> Res<u8> {
+        let miner_config = match Config::existing_config() {
+            true => Config::read_from_toml()?,
+            false => Config::default(),
+        };
+
+        let parent = match utils::read_tx(&args.solution) {
+            Ok(tx) => tx,
+            Err(e) => return fail(e.to_string(), EXIT_INVALID_INPUT),
+        };
+
+        let key = Cpfp::key(args.key.as_deref(), &miner_config.funding_key)?;
+
+        let to = match &args.to {
+            Some(to) => {
+                MagicMiner::resolve_address(&miner_config, to, true, 0)
+                    .await?
+                    .1
+            }
+            None => P2PKHAddress::from_pubkey(&key.to_public_key()?)?.get_locking_script()?,
+        };
+
+        let child = Cpfp::build_child(
+            &parent,
+            Cpfp::saved_fee(&args.solution),
+            &key,
+            &to,
+            args.fee_rate.unwrap_or(miner_config.fee_rate),
+        )?;
+
+        let child_hex = child.to_hex()?;
+
+        if args.no_publish {
+            output::emit(Event::RawTx {
+                txid: child.get_id_hex()?,
+                raw_tx: child_hex,
+            });
+            return Ok(EXIT_OK);
+        }
+
+        let result = Cpfp::broadcast_package(&[parent.to_hex()?, child_hex]).await;
+
+        output::emit(Event::Broadcast {
+            success: result.is_ok(),
+            response: match &result {
+                Ok(response) => response.trim().to_string(),
+                Err(e) => e.to_string(),
+            },
+            path: Some(args.solution.clone()),
+        });
+
+        match result {
+            Ok(_) => Ok(EXIT_OK),
+            Err(_) => Ok(EXIT_NETWORK),
+        }
+    }
+
+    pub async fn mine(args: MineArgs) -> Res<u8> {
+        let MineArgs {
+            txid,
+            raw,
+            vout,
+            pay_to,
+            threads,
+            no_publish,
+            offline,
+        } = args;
+
+        let mut miner_config = match Config::existing_config() {
+            true => match Config::read_from_toml() {
+                Ok(config) => config,
+                Err(e) => return fail(format!("Invalid miner config.\n{}", e), EXIT_CONFIG),
+            },
+            false => Config::default(),
+        };
+
+        if let Some(threads) = threads {
+            miner_config.threads = threads;
+        }
+
+        if no_publish || offline {
+            miner_config.autopublish = false;
+        }
+
+        if offline {
+            miner_config.autosave = true;
+        }
+
+        let (tx, vout) = match (raw, txid) {
+            (Some(raw), _) => {
+                let tx = match utils::read_tx(&raw) {
+                    Ok(tx) => tx,
+                    Err(e) => return fail(e.to_string(), EXIT_INVALID_INPUT),
+                };
+                (tx, vout)
+            }
+            (None, Some(input)) => {
+                let outpoint = match utils::parse_outpoint(&input) {
+                    Some(outpoint) => outpoint,
+                    None => return fail(format!("Invalid txid: {}", input), EXIT_INVALID_INPUT),
+                };
+
+                let tx = match offline {
+                    true => match TxCache::default().get(&outpoint.txid) {
+                        Some(tx) => tx,
+                        None => {
+                            return fail(
+                                format!("{} is not in the local cache", outpoint.txid),
+                                EXIT_INVALID_INPUT,
+                            )
+                        }
+                    },
+                    false => match MagicMiner::get_tx_any_order(&outpoint.txid).await {
+                        Ok(tx) => tx,
+                        Err(e) => return fail(e.to_string(), exit_code(&e)),
+                    },
+                };
+
+                if !offline && !miner_config.headers.is_empty() {
+                    MagicMiner::verify_inclusion(&tx.get_id_hex()?, &miner_config.headers).await?;
+                }
+
+                (tx, vout.or(outpoint.vout))
+            }
+            (None, None) => {
+                return fail(
+                    String::from("Either --txid or --raw is required"),
+                    EXIT_INVALID_INPUT,
+                )
+            }
+        };
+
+        let found = match vout {
+            Some(vout) => Some(MagicMiner::select_output(&tx, vout)?),
+            None => MagicMiner::find_21e8_output(&tx)?,
+        };
+
+        let (index, target) = match found {
+            Some(found) => found,
+            None => {
+                output::emit(Event::NoPuzzle {
+                    txid: tx.get_id_hex()?,
+                });
+                return Ok(EXIT_NO_PUZZLE);
+            }
+        };
+
+        // an explicit address replaces any configured split
+        if let Some(pay_to) = pay_to {
+            miner_config.pay_to = pay_to;
+            miner_config.payouts.clear();
+            miner_config.xpub.clear();
+        }
+
+        let fees = match MagicMiner::check_puzzle(&tx, index, &miner_config) {
+            Ok(fees) => fees,
+            Err(e) => return fail(e.to_string(), EXIT_INSUFFICIENT_VALUE),
+        };
+
+        if miner_config.pay_to.is_empty()
+            && miner_config.payouts.is_empty()
+            && miner_config.xpub.is_empty()
+        {
+            return fail(
+                String::from("No payout address, use --pay-to or run setup"),
+                EXIT_CONFIG,
+            );
+        }
+
+        let payouts = match miner_config.payouts.is_empty() && miner_config.xpub.is_empty() {
+            true => MagicMiner::resolve_address(
+                &miner_config,
+                &miner_config.pay_to,
+                offline,
+                fees.payout,
+            )
+            .await
+            .map(|(address, script)| Payout::single(address, script)),
+            false => MagicMiner::resolve_payouts(&miner_config, offline, fees.payout).await,
+        };
+
+        let payouts = match payouts {
+            Ok(payouts) => payouts,
+            Err(e) => return fail(e.to_string(), EXIT_CONFIG),
+        };
+
+        for payout in &payouts {
+            output::emit(Event::Payout {
+                address: payout.address.clone(),
+            });
+        }
+
+        MagicMiner::solve_puzzle(tx, index, &target, payouts, miner_config).await?;
+
+        Ok(EXIT_OK)
+    }
+
+    pub async fn inspect(input: &str) -> Res<u8> {
+        let outpoint = match utils::parse_outpoint(input) {
+            Some(outpoint) => outpoint,
+            None => {
+                output::emit(Event::Error {
+                    message: format!("Invalid txid: {}", input),
+                });
+                return Ok(EXIT_INVALID_INPUT);
+            }
+        };
+
+        let tx = match MagicMiner::get_tx_any_order(&outpoint.txid).await {
+            Ok(tx) => tx,
+            Err(e) => {
+                output::emit(Event::Error {
+                    message: e.to_string(),
+                });
+                return Ok(exit_code(&e));
+            }
+        };
+
+        let puzzles = MagicMiner::find_21e8_outputs(&tx)?;
+
+        for (index, target) in &puzzles {
+            output::emit(Event::Output {
+                vout: *index,
+                target: hex::encode(target),
+                satoshis: tx.get_output(*index).map(|o| o.get_satoshis()).unwrap_or(0),
+            });
+        }
+
+        match puzzles.is_empty() {
+            true => {
+                output::emit(Event::NoPuzzle {
+                    txid: tx.get_id_hex()?,
+                });
+                Ok(EXIT_NO_PUZZLE)
+            }
+            false => Ok(EXIT_OK),
+        }
+    }
+
+    pub async fn verify(input: &str) -> Res<u8> {
+        let tx = match utils::read_tx(input) {
+            Ok(tx) => tx,
+            Err(e) => {
+                output::emit(Event::Error {
+                    message: e.to_string(),
+                });
+                return Ok(EXIT_INVALID_INPUT);
+            }
+        };
+
+        let prev_txid = match tx.get_input(0) {
+            Some(input) => input.get_prev_tx_id_hex(None),
+            None => return Ok(EXIT_INVALID_SOLUTION),
+        };
+
+        let source = match MagicMiner::get_tx(&prev_txid).await {
+            Ok(source) => source,
+            Err(e) => {
+                output::emit(Event::Error {
+                    message: e.to_string(),
+                });
+                return Ok(exit_code(&e));
+            }
+        };
+
+        let valid = MagicMiner::verify_solution(&tx, &source)?;
+
+        output::emit(Event::Verified {
+            txid: tx.get_id_hex()?,
+            valid,
+        });
+
+        match valid {
+            true => Ok(EXIT_OK),
+            false => Ok(EXIT_INVALID_SOLUTION),
+        }
+    }
+
+    pub async fn publish(dir: &str) -> Res<u8> {
+        let mut code = EXIT_OK;
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                continue;
+            }
+
+            let tx_hex = std::fs::read_to_string(&path)?;
+
+            let result = MagicMiner::broadcast_tx(tx_hex.trim()).await;
+
+            output::emit(Event::Broadcast {
+                success: result.is_ok(),
+                response: match &result {
+                    Ok(response) => response.trim().to_string(),
+                    Err(e) => e.to_string(),
+                },
+                path: Some(path.display().to_string()),
+            });
+
+            if result.is_err() {
+                code = EXIT_NETWORK;
+            }
+        }
+
+        Ok(code)
+    }
+}
+
+/// Entry point for the `start` binary, falls back to the interactive menu without a subcommand
+pub async fn run() -> ExitCode {
+    let args = Args::parse();
+
+    Config::set_path(&args.config);
+
+    if args.json {
+        output::set_mode(OutputMode::Json);
+    } else if args.tui {
+        output::set_mode(OutputMode::Dashboard);
+    }
+
+    // the dashboard owns the terminal, so it only gets file logs
+    let _log_guard = match logging::init(args.verbose, !args.tui, args.log_dir.as_deref()) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Could not set up logging: {:?}", e);
+            None
+        }
+    };
+
+    if let Some(addr) = &args.metrics {
+        if let Err(e) = metrics::serve(addr).await {
+            eprintln!("Could not start metrics endpoint on {}: {:?}", addr, e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    }
+
+    let result = match args.command {
+        Some(command) => CLI::dispatch(command).await,
+        None => start().await.map(|_| EXIT_OK),
+    };
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            output::emit(Event::Error {
+                message: e.to_string(),
+            });
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Entry point of the `setup` binary, the same flow as `start setup`
+pub fn run_setup() -> ExitCode {
+    let command = SetupCommand::parse();
+
+    Config::set_path(&command.config);
+
+    let _ = logging::init(0, true, None);
+
+    match CLI::setup(command.args) {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            output::emit(Event::Error {
+                message: e.to_string(),
+            });
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+/// Reports a failure as an `error` event, so `--json` consumers see it too
+fn fail(message: String, code: u8) -> Res<u8> {
+    output::emit(Event::Error { message });
+    Ok(code)
+}
+
+fn exit_code(error: &MinerError) -> u8 {
+    match error {
+        MinerError::Network(_) => EXIT_NETWORK,
+        MinerError::Parse(_) => EXIT_INVALID_INPUT,
+        MinerError::Config(_) => EXIT_CONFIG,
+        MinerError::ScriptMismatch(_) => EXIT_NO_PUZZLE,
+        MinerError::InsufficientValue { .. } => EXIT_INSUFFICIENT_VALUE,
+        MinerError::TxidMismatch { .. } | MinerError::MerkleProof(_) => EXIT_VERIFICATION,
+        _ => EXIT_ERROR,
+    }
 }
 
 pub async fn start() -> Res<()> {
-    CLI::menu(vec![SelectOption::new("Start"), SelectOption::new("Setup")]).await
+    CLI::menu(vec![
+        SelectOption::new("Start"),
+        SelectOption::new("Offline"),
+        SelectOption::new("Setup"),
+        SelectOption::new("Import"),
+    ])
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_takes_answers_as_flags() {
+        let args = Args::try_parse_from([
+            "start",
+            "setup",
+            "--force",
+            "--pay-to",
+            "alice@example.com",
+            "--autosave",
+            "false",
+            "--priv-key",
+            "",
+        ])
+        .unwrap();
+
+        let args = match args.command {
+            Some(Command::Setup(args)) => args,
+            _ => panic!("expected the setup command"),
+        };
+
+        assert!(args.force);
+        assert_eq!(args.answers.pay_to.as_deref(), Some("alice@example.com"));
+        assert_eq!(args.answers.autosave, Some(false));
+        assert_eq!(args.answers.priv_key.as_deref(), Some(""));
+
+        // anything left out is prompted for
+        assert_eq!(args.answers.miner_id, None);
+        assert_eq!(args.answers.message, None);
+        assert_eq!(args.answers.autopublish, None);
+
+        assert!(Args::try_parse_from(["start", "setup", "--autosave", "maybe"]).is_err());
+    }
+
+    #[test]
+    fn setup_binary_takes_the_same_flags() {
+        let command =
+            SetupCommand::try_parse_from(["setup", "-c", "miner.toml", "--autopublish", "true"])
+                .unwrap();
+
+        assert_eq!(command.config, "miner.toml");
+        assert!(!command.args.force);
+        assert_eq!(command.args.answers.autopublish, Some(true));
+        assert_eq!(command.args.answers.pay_to, None);
+    }
+
+    #[test]
+    fn job_embeds_or_references_but_not_both() {
+        let job = |extra: &[&str]| {
+            let mut args = vec!["start", "job", "--text", "hi", "--satoshis", "1000"];
+            args.extend_from_slice(extra);
+            Args::try_parse_from(args)
+        };
+
+        assert!(job(&["--embed"]).is_ok());
+        assert!(job(&["--reference", "https://example.com/hi.txt"]).is_ok());
+        assert!(job(&["--embed", "--reference", "https://example.com/hi.txt"]).is_err());
+        assert!(Args::try_parse_from([
+            "start",
+            "job",
+            "--hash",
+            "aa",
+            "--satoshis",
+            "1",
+            "--embed"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn errors_map_to_documented_exit_codes() {
+        let cases = [
+            (MinerError::Network(String::new()), EXIT_NETWORK),
+            (MinerError::Parse(String::new()), EXIT_INVALID_INPUT),
+            (MinerError::Config(String::new()), EXIT_CONFIG),
+            (MinerError::ScriptMismatch(String::new()), EXIT_NO_PUZZLE),
+            (
+                MinerError::InsufficientValue {
+                    value: 1,
+                    required: 2,
+                },
+                EXIT_INSUFFICIENT_VALUE,
+            ),
+            (
+                MinerError::TxidMismatch {
+                    requested: String::from("aa"),
+                    received: String::from("bb"),
+                },
+                EXIT_VERIFICATION,
+            ),
+            (MinerError::MerkleProof(String::new()), EXIT_VERIFICATION),
+            (MinerError::Signing(String::new()), EXIT_ERROR),
+            (
+                MinerError::Io(std::io::Error::new(std::io::ErrorKind::Other, "io")),
+                EXIT_ERROR,
+            ),
+            (
+                MinerError::Worker {
+                    thread: 0,
+                    cause: String::new(),
+                },
+                EXIT_ERROR,
+            ),
+            (MinerError::Cancelled, EXIT_ERROR),
+        ];
+
+        for (error, code) in cases {
+            assert_eq!(exit_code(&error), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn conversions_pick_the_error_class() {
+        assert!(matches!(
+            MinerError::from(hex::decode("zz").unwrap_err()),
+            MinerError::Parse(_)
+        ));
+        assert!(matches!(
+            MinerError::from("x".parse::<u32>().unwrap_err()),
+            MinerError::Parse(_)
+        ));
+        assert!(matches!(
+            MinerError::from(toml::from_str::<Config>("pay_to = ").unwrap_err()),
+            MinerError::Config(_)
+        ));
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [
+            EXIT_OK,
+            EXIT_ERROR,
+            EXIT_INVALID_INPUT,
+            EXIT_NO_PUZZLE,
+            EXIT_CONFIG,
+            EXIT_NETWORK,
+            EXIT_INVALID_SOLUTION,
+            EXIT_INSUFFICIENT_VALUE,
+            EXIT_VERIFICATION,
+        ];
+
+        let unique: std::collections::HashSet<u8> = codes.iter().copied().collect();
+        assert_eq!(unique.len(), codes.len());
+        // 2 is reserved for clap usage errors
+        assert!(!unique.contains(&2));
+    }
 }
@@ -1,11 +1,12 @@
-use crate::Prompt;
-use crate::Res;
+use crate::fee::DEFAULT_FEE_RATE;
+use crate::payout::PayoutRule;
+use crate::{MinerError, Res};
 use bsv::PrivateKey;
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
 use std::path::Path;
-use toml::de::Error as TomlError;
+use std::sync::OnceLock;
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MinerIDConfig {
@@ -20,23 +21,66 @@ pub struct Config {
     pub pay_to: String,
     pub autopublish: bool,
     pub autosave: bool,
+    #[serde(default)]
+    pub headers: String,
+    #[serde(default)]
+    pub threads: usize,
+    /// Satoshis per 1000 bytes
+    #[serde(default = "default_fee_rate")]
+    pub fee_rate: u64,
+    /// Split payouts, used instead of `pay_to` when not empty
+    #[serde(default)]
+    pub payouts: Vec<PayoutRule>,
+    /// Extended public key to derive a fresh payout address from for every solution
+    #[serde(default)]
+    pub xpub: String,
+    #[serde(default = "default_derivation_path")]
+    pub derivation_path: String,
+    /// WIF or xprv whose UTXOs pay the fee of puzzles too small to pay their own
+    #[serde(default)]
+    pub funding_key: String,
+    /// WIF, or xprv matching `xpub`, used to sweep payouts
+    #[serde(default)]
+    pub payout_key: String,
+    /// Pay Paymail outputs that are not signed by the host's PKI key
+    #[serde(default)]
+    pub allow_unsigned_paymail: bool,
 }
 
-#[derive(Deserialize)]
-pub struct PolynymResponse {
-    address: String,
+fn default_fee_rate() -> u64 {
+    DEFAULT_FEE_RATE
 }
 
+fn default_derivation_path() -> String {
+    String::from("m/0")
+}
+
+/// Quotes and escapes `value` so paths and messages survive the round trip
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+static CONFIG_PATH: OnceLock<String> = OnceLock::new();
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             pay_to: String::from(""),
             autopublish: true,
             autosave: true,
+            headers: String::from(""),
+            threads: 0,
+            fee_rate: DEFAULT_FEE_RATE,
+            payouts: Vec::new(),
+            xpub: String::from(""),
+            derivation_path: default_derivation_path(),
+            funding_key: String::from(""),
+            payout_key: String::from(""),
+            allow_unsigned_paymail: false,
             miner_id: {
                 MinerIDConfig {
                     enabled: false,
-                    priv_key: PrivateKey::from_random().to_wif().unwrap(),
+                    priv_key: PrivateKey::from_random().to_wif().unwrap_or_default(),
                     message: String::from(""),
                 }
             },
@@ -54,121 +98,260 @@ impl Config {
         priv_key: String,
         message: String,
     ) -> Self {
-        Config {
-            pay_to,
-            autopublish,
-            autosave,
-            miner_id: {
-                MinerIDConfig {
-                    enabled,
-                    priv_key,
-                    message,
-                }
-            },
-        }
+        Config::default().with_setup(pay_to, autopublish, autosave, enabled, priv_key, message)
+    }
+
+    /// Replaces only what setup asks for, keeping payouts, keys and tuning
+    pub fn with_setup(
+        mut self,
+        pay_to: String,
+        autopublish: bool,
+        autosave: bool,
+        enabled: bool,
+        priv_key: String,
+        message: String,
+    ) -> Self {
+        self.pay_to = pay_to;
+        self.autopublish = autopublish;
+        self.autosave = autosave;
+        self.miner_id = MinerIDConfig {
+            enabled,
+            priv_key,
+            message,
+        };
+        self
     }
 
     fn to_formatted_string(
         pay_to: &str,
         autopublish: &str,
         autosave: &str,
+        headers: &str,
+        threads: &str,
+        fee_rate: &str,
+        xpub: &str,
+        derivation_path: &str,
+        funding_key: &str,
+        payout_key: &str,
+        allow_unsigned_paymail: &str,
         enabled: &str,
         priv_key: &str,
         message: &str,
     ) -> String {
         format!(
             concat!(
-                "# Pay solved puzzles out to P2PKH address\n",
-                "pay_to = \"{}\"\n",
+                "# Pay solved puzzles out to a P2PKH address, handle, Paymail or script\n",
+                "pay_to = {}\n",
                 "# Automatically publish solved puzzles\n",
                 "autopublish = {}\n",
                 "autosave = {}\n",
+                "# Verify merkle proofs against block headers in this file (optional)\n",
+                "headers = {}\n",
+                "# Number of mining threads (0 uses all available cores)\n",
+                "threads = {}\n",
+                "# Mining fee in satoshis per 1000 bytes\n",
+                "fee_rate = {}\n",
+                "# Derive a fresh payout address per solution from this xpub (optional)\n",
+                "xpub = {}\n",
+                "derivation_path = {}\n",
+                "# WIF or xprv to fund the fee of puzzles too small to pay it (optional)\n",
+                "funding_key = {}\n",
+                "# WIF, or xprv matching xpub, to sweep payouts with (optional)\n",
+                "payout_key = {}\n",
+                "# Pay Paymail outputs the host did not sign with its PKI key\n",
+                "allow_unsigned_paymail = {}\n",
                 "\n[miner_id]\n",
                 "# Enable Miner API\n",
                 "enabled = {}\n",
                 "# Private key in WIF format\n",
-                "priv_key = \"{}\"\n",
+                "priv_key = {}\n",
                 "# Select a message for Miner API\n",
-                "message = \"{}\""
+                "message = {}"
             ),
-            pay_to, autopublish, autosave, enabled, priv_key, message
+            pay_to,
+            autopublish,
+            autosave,
+            headers,
+            threads,
+            fee_rate,
+            xpub,
+            derivation_path,
+            funding_key,
+            payout_key,
+            allow_unsigned_paymail,
+            enabled,
+            priv_key,
+            message
         )
     }
 
     fn to_toml_string(&self) -> String {
-        Config::to_formatted_string(
-            &self.pay_to,
+        let mut toml = Config::to_formatted_string(
+            &toml_string(&self.pay_to),
             &self.autopublish.to_string(),
             &self.autosave.to_string(),
+            &toml_string(&self.headers),
+            &self.threads.to_string(),
+            &self.fee_rate.to_string(),
+            &toml_string(&self.xpub),
+            &toml_string(&self.derivation_path),
+            &toml_string(&self.funding_key),
+            &toml_string(&self.payout_key),
+            &self.allow_unsigned_paymail.to_string(),
             &self.miner_id.enabled.to_string(),
-            &self.miner_id.priv_key,
-            &self.miner_id.message,
-        )
+            &toml_string(&self.miner_id.priv_key),
+            &toml_string(&self.miner_id.message),
+        );
+
+        if self.payouts.is_empty() {
+            toml.push_str(concat!(
+                "\n\n# Split payouts, used instead of pay_to. Each recipient takes a fixed\n",
+                "# amount or a percent of the rest, one without either takes the remainder\n",
+                "# [[payouts]]\n",
+                "# address = \"1...\"\n",
+                "# percent = 50"
+            ));
+        }
+
+        for rule in &self.payouts {
+            toml.push_str(&format!(
+                "\n\n[[payouts]]\naddress = {}",
+                toml_string(&rule.address)
+            ));
+
+            if let Some(percent) = rule.percent {
+                toml.push_str(&format!("\npercent = {}", percent));
+            }
+
+            if let Some(amount) = rule.amount {
+                toml.push_str(&format!("\namount = {}", amount));
+            }
+        }
+
+        toml
     }
 
-    pub fn from_toml_str(s: &str) -> Result<Config, TomlError> {
-        toml::from_str::<Config>(s)
+    pub fn from_toml_str(s: &str) -> Res<Config> {
+        Ok(toml::from_str::<Config>(s)?)
     }
 
     pub fn to_toml_bytes(self) -> Vec<u8> {
         self.to_toml_string().into_bytes()
     }
 
+    /// Overrides the default `Config.toml` location, only the first call has any effect
+    pub fn set_path(path: &str) {
+        let _ = CONFIG_PATH.set(path.to_string());
+    }
+
+    pub fn path() -> &'static str {
+        CONFIG_PATH
+            .get()
+            .map(String::as_str)
+            .unwrap_or("Config.toml")
+    }
+
     pub fn existing_config() -> bool {
-        Path::new("Config.toml").exists()
+        Path::new(Config::path()).exists()
     }
 
-    pub fn read_from_toml() -> Result<Config, TomlError> {
+    pub fn read_from_toml() -> Res<Config> {
         let mut file: File = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
-            .open("Config.toml")
-            .expect("Could not open Config.toml");
+            .open(Config::path())
+            .map_err(|e| MinerError::Config(format!("could not open {}: {}", Config::path(), e)))?;
 
         let mut content = String::new();
-        file.read_to_string(&mut content).unwrap();
+        file.read_to_string(&mut content)?;
 
         Config::from_toml_str(&content)
     }
 
-    pub fn write_to_toml(config: Config) {
-        let mut file = File::create("Config.toml").unwrap();
-        file.write_all(&config.to_toml_bytes()).unwrap();
+    pub fn write_to_toml(config: Config) -> Res<()> {
+        let mut file = File::create(Config::path())?;
+        file.write_all(&config.to_toml_bytes())?;
+        Ok(())
     }
+}
 
-    pub async fn fetch_polynym_address(input: &str) -> Res<String> {
-        let url = format!("https://api.polynym.io/getAddress/{}", input);
-        let p2pkh_address = reqwest::Client::new()
-            .get(url)
-            .send()
-            .await?
-            .json::<PolynymResponse>()
-            .await?;
-        Ok(p2pkh_address.address)
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn optional_setup() -> Res<Config> {
-        match asky::Confirm::new("Would you like to set up miner ID?").prompt()? {
-            true => Prompt::run_setup(),
-            false => Ok(Config::default()),
-        }
-    }
-}
+    #[test]
+    fn strings_survive_the_round_trip() {
+        let mut config = Config::default();
+        config.pay_to = String::from("alice@example.com");
+        config.headers = String::from(r"C:\miner\headers.bin");
+        config.xpub = String::from("xpub\"quoted\"");
+        config.funding_key = String::from("back\\slash");
+        config.miner_id.message = String::from("say \"hi\"\nand 'bye'");
+        config.payouts = vec![PayoutRule {
+            address: String::from("1\"odd"),
+            percent: Some(50.0),
+            amount: None,
+        }];
 
-pub fn init() -> Res<()> {
-    let found_config = Config::existing_config();
+        let toml = config.to_toml_string();
+        let parsed = Config::from_toml_str(&toml).unwrap();
 
-    let run_setup = match found_config {
-        true => {
-            asky::Confirm::new("Found existing config. Would you like to overwrite it?").prompt()?
-        }
-        false => false,
-    };
+        assert_eq!(parsed.pay_to, config.pay_to);
+        assert_eq!(parsed.headers, config.headers);
+        assert_eq!(parsed.xpub, config.xpub);
+        assert_eq!(parsed.funding_key, config.funding_key);
+        assert_eq!(parsed.derivation_path, "m/0");
+        assert_eq!(parsed.miner_id.message, config.miner_id.message);
+        assert_eq!(parsed.miner_id.priv_key, config.miner_id.priv_key);
+        assert_eq!(parsed.payouts[0].address, "1\"odd");
+        assert_eq!(parsed.payouts[0].percent, Some(50.0));
 
-    if run_setup {
-        Config::optional_setup()?;
+        // the comments stay in place
+        assert!(toml.contains("# Verify merkle proofs against block headers"));
     }
 
-    Ok(())
+    #[test]
+    fn setup_keeps_everything_it_does_not_ask_for() {
+        let mut config = Config::default();
+        config.headers = String::from("headers.bin");
+        config.threads = 3;
+        config.fee_rate = 100;
+        config.xpub = String::from("xpub6");
+        config.derivation_path = String::from("m/1");
+        config.funding_key = String::from("funding");
+        config.payout_key = String::from("payout");
+        config.allow_unsigned_paymail = true;
+        config.payouts = vec![PayoutRule {
+            address: String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"),
+            percent: None,
+            amount: Some(1_000),
+        }];
+
+        let updated = config.clone().with_setup(
+            String::from("alice@example.com"),
+            false,
+            false,
+            true,
+            String::from("wif"),
+            String::from("hello"),
+        );
+
+        assert_eq!(updated.pay_to, "alice@example.com");
+        assert!(!updated.autopublish && !updated.autosave);
+        assert!(updated.miner_id.enabled);
+        assert_eq!(updated.miner_id.priv_key, "wif");
+        assert_eq!(updated.miner_id.message, "hello");
+
+        assert_eq!(updated.headers, config.headers);
+        assert_eq!(updated.threads, 3);
+        assert_eq!(updated.fee_rate, 100);
+        assert_eq!(updated.xpub, config.xpub);
+        assert_eq!(updated.derivation_path, "m/1");
+        assert_eq!(updated.funding_key, "funding");
+        assert_eq!(updated.payout_key, "payout");
+        assert!(updated.allow_unsigned_paymail);
+        assert_eq!(updated.payouts.len(), 1);
+        assert_eq!(updated.payouts[0].amount, Some(1_000));
+    }
 }
@@ -0,0 +1,252 @@
+use crate::fee::{fee_for_size, FeeBreakdown};
+use crate::funding::sign_p2pkh;
+use crate::metrics::metrics;
+use crate::{Funding, MinerError, Res, DUST_LIMIT};
+use bsv::{P2PKHAddress, PrivateKey, Script, Transaction, TxIn, TxOut};
+use serde_json::{json, Value};
+
+/// ARC endpoint that accepts a parent and child in one request
+pub const ARC_URL: &str = "https://arc.gorillapool.io";
+
+/// Child-pays-for-parent fee bumping of saved solutions
+pub struct Cpfp;
+
+impl Cpfp {
+    /// Fee recorded in the metadata next to a saved solution, 0 when unknown
+    pub fn saved_fee(solution_path: &str) -> u64 {
+        let path = std::path::Path::new(solution_path).with_extension("json");
+
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+            .and_then(|metadata| metadata["fee"].as_u64())
+            .unwrap_or(0)
+    }
+
+    /// Spends the first output of `parent` locked to `key` into `to`, paying enough that
+    /// parent and child together reach `fee_rate`
+    pub fn build_child(
+        parent: &Transaction,
+        parent_fee: u64,
+        key: &PrivateKey,
+        to: &Script,
+        fee_rate: u64,
+    ) -> Res<Transaction> {
+        let address = P2PKHAddress::from_pubkey(&key.to_public_key()?)?;
+        let locking_script = address.get_locking_script()?;
+
+        let vout = (0..parent.get_noutputs())
+            .find(|i| {
+                parent.get_output(*i).map_or(false, |o| {
+                    o.get_script_pub_key().to_bytes() == locking_script.to_bytes()
+                })
+            })
+            .ok_or_else(|| {
+                MinerError::ScriptMismatch(format!(
+                    "no output pays to {}",
+                    address.to_string().unwrap_or_default()
+                ))
+            })?;
+
+        let value = parent
+            .get_output(vout)
+            .map(|o| o.get_satoshis())
+            .unwrap_or_default();
+
+        let mut tx_in = TxIn::default();
+
+        tx_in.set_satoshis(value);
+        tx_in.set_locking_script(&locking_script);
+        tx_in.set_prev_tx_id(&parent.get_id_bytes()?);
+        tx_in.set_vout(vout as u32);
+
+        let mut child = Transaction::new(1, 0);
+        child.add_input(&tx_in);
+        child.add_output(&TxOut::new(0u64, to));
+
+        let child_size = FeeBreakdown::estimate(&child, value, fee_rate)?.size;
+        let parent_size = parent.to_bytes()?.len();
+
+        let package_fee = fee_for_size(parent_size + child_size, fee_rate);
+        let child_fee = package_fee.saturating_sub(parent_fee).max(1);
+        let required = child_fee + DUST_LIMIT;
+
+        if value < required {
+            return Err(MinerError::InsufficientValue { value, required });
+        }
+
+        tracing::info!(parent_size, child_size, parent_fee, child_fee, "fee bump");
+
+        child.set_output(0, &TxOut::new(value - child_fee, to));
+
+        sign_p2pkh(&mut child, 0, key, value)?;
+
+        Ok(child)
+    }
+
+    /// Submits the transactions to ARC in one request, parents first
+    pub async fn broadcast_package(txs: &[String]) -> Res<String> {
+        let body: Vec<Value> = txs.iter().map(|tx| json!({ "rawTx": tx })).collect();
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/txs", ARC_URL))
+            .json(&body)
+            .send()
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                metrics().broadcast("arc", false);
+                return Err(e.into());
+            }
+        };
+
+        let status = response.status();
+        let body = response.text().await?;
+
+        metrics().broadcast("arc", status.is_success());
+
+        if !status.is_success() {
+            return Err(MinerError::Network(format!(
+                "package broadcast failed ({}): {}",
+                status, body
+            )));
+        }
+
+        Ok(body)
+    }
+
+    /// Key for the payout being bumped, the funding key when none is given
+    pub fn key(key: Option<&str>, funding_key: &str) -> Res<PrivateKey> {
+        match key {
+            Some(key) => Funding::key(key),
+            None if !funding_key.is_empty() => Funding::key(funding_key),
+            None => Err(MinerError::Config(String::from(
+                "no key for the payout, use --key or set funding_key",
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OTHER: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+
+    fn parent(key: &PrivateKey, value: u64) -> Transaction {
+        let payout = P2PKHAddress::from_pubkey(&key.to_public_key().unwrap())
+            .unwrap()
+            .get_locking_script()
+            .unwrap();
+        let other = P2PKHAddress::from_string(OTHER)
+            .unwrap()
+            .get_locking_script()
+            .unwrap();
+
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&TxIn::new(&[1u8; 32], 0, &Script::default(), None));
+        tx.add_output(&TxOut::new(700u64, &other));
+        tx.add_output(&TxOut::new(value, &payout));
+        tx
+    }
+
+    fn to() -> Script {
+        P2PKHAddress::from_string(OTHER)
+            .unwrap()
+            .get_locking_script()
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_the_fee_from_saved_metadata() {
+        let dir = std::env::temp_dir().join(format!("magic_miner_cpfp_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let solution = dir.join("solution.hex");
+        std::fs::write(dir.join("solution.json"), r#"{"fee": 123}"#).unwrap();
+        assert_eq!(Cpfp::saved_fee(solution.to_str().unwrap()), 123);
+
+        std::fs::write(dir.join("solution.json"), "not json").unwrap();
+        assert_eq!(Cpfp::saved_fee(solution.to_str().unwrap()), 0);
+
+        assert_eq!(
+            Cpfp::saved_fee(dir.join("missing.hex").to_str().unwrap()),
+            0
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn child_tops_the_package_up_to_the_fee_rate() {
+        let key = PrivateKey::from_random();
+        let parent = parent(&key, 10_000);
+        let parent_size = parent.to_bytes().unwrap().len() as u64;
+
+        let child = Cpfp::build_child(&parent, 5, &key, &to(), 1000).unwrap();
+
+        // one signed input and one P2PKH output
+        let child_fee = parent_size + 193 - 5;
+        assert_eq!(
+            child.get_output(0).unwrap().get_satoshis(),
+            10_000 - child_fee
+        );
+
+        let input = child.get_input(0).unwrap();
+        assert_eq!(input.get_prev_tx_id(None), parent.get_id_bytes().unwrap());
+        assert_eq!(input.get_vout(), 1);
+        assert!(!input.get_unlocking_script().to_bytes().is_empty());
+    }
+
+    #[test]
+    fn child_pays_at_least_one_satoshi() {
+        let key = PrivateKey::from_random();
+        let parent = parent(&key, 10_000);
+
+        let child = Cpfp::build_child(&parent, 100_000, &key, &to(), 50).unwrap();
+
+        assert_eq!(child.get_output(0).unwrap().get_satoshis(), 10_000 - 1);
+    }
+
+    #[test]
+    fn refuses_parents_it_cannot_bump() {
+        let key = PrivateKey::from_random();
+
+        match Cpfp::build_child(
+            &parent(&key, 10_000),
+            0,
+            &PrivateKey::from_random(),
+            &to(),
+            50,
+        ) {
+            Err(MinerError::ScriptMismatch(_)) => {}
+            other => panic!("unexpected result {:?}", other.map(|tx| tx.to_hex())),
+        }
+
+        let parent = parent(&key, 300);
+        let parent_size = parent.to_bytes().unwrap().len() as u64;
+
+        match Cpfp::build_child(&parent, 0, &key, &to(), 1000) {
+            Err(MinerError::InsufficientValue { value, required }) => {
+                assert_eq!((value, required), (300, parent_size + 193 + DUST_LIMIT));
+            }
+            other => panic!("unexpected result {:?}", other.map(|tx| tx.to_hex())),
+        }
+    }
+
+    #[test]
+    fn picks_the_given_key_then_the_funding_key() {
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        let other = PrivateKey::from_random().to_wif().unwrap();
+
+        let key = Cpfp::key(Some(wif), &other).unwrap();
+        assert_eq!(key.to_wif().unwrap(), wif);
+
+        let key = Cpfp::key(None, wif).unwrap();
+        assert_eq!(key.to_wif().unwrap(), wif);
+
+        assert!(matches!(Cpfp::key(None, ""), Err(MinerError::Config(_))));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::output::{self, Event};
+use crate::stats::MiningStats;
+use crate::Res;
+use crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph};
+use ratatui::{Frame, Terminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_LOG_LINES: usize = 100;
+
+pub struct DashboardJob {
+    pub txid: String,
+    pub vout: usize,
+    pub target: String,
+    pub satoshis: u64,
+    pub payout: String,
+}
+
+/// Full-screen view of a running job, shown instead of the `\r` progress line
+pub struct Dashboard {
+    handle: Option<JoinHandle<()>>,
+    finished: Arc<AtomicBool>,
+    quit: Arc<AtomicBool>,
+}
+
+struct DashboardState {
+    job: DashboardJob,
+    stats: Arc<MiningStats>,
+    log: Vec<String>,
+    last_sample: (Instant, Vec<u64>),
+    thread_rates: Vec<f64>,
+    finished: bool,
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    match secs {
+        s if s >= 86400 => format!("{}d {}h", s / 86400, (s % 86400) / 3600),
+        s if s >= 3600 => format!("{}h {}m", s / 3600, (s % 3600) / 60),
+        s => format!("{}m {}s", s / 60, s % 60),
+    }
+}
+
+fn format_event(event: &Event) -> Option<String> {
+    match event {
+        Event::Solution { hash, .. } => Some(format!("🪄 solved {}", hash)),
+        Event::Summary { attempts, luck, .. } => Some(match luck {
+            Some(luck) => format!("{} attempts, luck {:.2}", attempts, luck),
+            None => format!("{} attempts", attempts),
+        }),
+        Event::Broadcast {
+            success, response, ..
+        } => match success {
+            true => Some(format!("broadcast ok {}", response)),
+            false => Some(format!("broadcast failed {}", response)),
+        },
+        Event::Saved { path } => Some(format!("saved to {}", path)),
+        Event::Error { message } => Some(format!("error {}", message)),
+        _ => None,
+    }
+}
+
+impl DashboardState {
+    fn sample(&mut self) {
+        let now = Instant::now();
+        let counts = self.stats.thread_attempts();
+        let secs = now.duration_since(self.last_sample.0).as_secs_f64();
+
+        if secs > 0.0 {
+            self.thread_rates = counts
+                .iter()
+                .zip(&self.last_sample.1)
+                .map(|(now, before)| (now - before) as f64 / secs)
+                .collect();
+        }
+
+        self.last_sample = (now, counts);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(7),
+                Constraint::Length(6),
+                Constraint::Min(4),
+                Constraint::Length(3),
+                Constraint::Min(5),
+            ])
+            .split(frame.size());
+
+        let job = Paragraph::new(vec![
+            Line::from(format!("txid     {}", self.job.txid)),
+            Line::from(format!("vout     {}", self.job.vout)),
+            Line::from(format!("target   {}", self.job.target)),
+            Line::from(format!("value    {} sats", self.job.satoshis)),
+            Line::from(format!("payout   {}", self.job.payout)),
+        ])
+        .block(Block::default().borders(Borders::ALL).title(" job "));
+
+        let status = match (self.finished, self.stats.is_paused()) {
+            (true, _) => "finished",
+            (false, true) => "paused",
+            (false, false) => "mining",
+        };
+
+        let eta = match self.stats.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("-"),
+        };
+
+        let progress = Paragraph::new(vec![
+            Line::from(format!("status       {}", status)),
+            Line::from(format!("attempts     {}", self.stats.attempts())),
+            Line::from(format!(
+                "best prefix  {}/{}",
+                self.stats.best_prefix(),
+                self.job.target.len()
+            )),
+            Line::from(format!(
+                "elapsed      {}   eta {}",
+                format_duration(self.stats.elapsed()),
+                eta
+            )),
+        ])
+        .block(Block::default().borders(Borders::ALL).title(" progress "));
+
+        let threads: Vec<ListItem> = self
+            .thread_rates
+            .iter()
+            .enumerate()
+            .map(|(i, rate)| ListItem::new(format!("thread {:>3}  {:>12.1} H/s", i, rate)))
+            .collect();
+
+        let total: f64 = self.thread_rates.iter().sum();
+
+        let threads = List::new(threads).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!(" threads ({:.1} H/s) ", total)),
+        );
+
+        let help = match self.finished {
+            true => "[q] quit",
+            false => "[p] pause  [r] resume  [c] cancel",
+        };
+
+        let help = Paragraph::new(help)
+            .style(Style::default().fg(Color::Cyan))
+            .block(Block::default().borders(Borders::ALL));
+
+        let log: Vec<ListItem> = self
+            .log
+            .iter()
+            .rev()
+            .map(|line| ListItem::new(line.as_str()))
+            .collect();
+
+        let log = List::new(log).block(Block::default().borders(Borders::ALL).title(" log "));
+
+        frame.render_widget(job, areas[0]);
+        frame.render_widget(progress, areas[1]);
+        frame.render_widget(threads, areas[2]);
+        frame.render_widget(help, areas[3]);
+        frame.render_widget(log, areas[4]);
+    }
+}
+
+impl Dashboard {
+    pub fn spawn(job: DashboardJob, stats: Arc<MiningStats>) -> Res<Dashboard> {
+        let (sender, receiver) = mpsc::channel::<Event>();
+        output::attach(sender);
+
+        enable_raw_mode()?;
+        execute!(std::io::stdout(), EnterAlternateScreen)?;
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let quit = Arc::new(AtomicBool::new(false));
+
+        let state = DashboardState {
+            job,
+            last_sample: (Instant::now(), vec![0; stats.threads()]),
+            thread_rates: vec![0.0; stats.threads()],
+            stats,
+            log: Vec::new(),
+            finished: false,
+        };
+
+        let finished_clone = Arc::clone(&finished);
+        let quit_clone = Arc::clone(&quit);
+
+        let handle = std::thread::spawn(move || {
+            if let Err(e) = Dashboard::run(state, receiver, &finished_clone, &quit_clone) {
+                tracing::error!(error = ?e, "dashboard stopped");
+            }
+
+            let _ = disable_raw_mode();
+            let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+        });
+
+        Ok(Dashboard {
+            handle: Some(handle),
+            finished,
+            quit,
+        })
+    }
+
+    fn run(
+        mut state: DashboardState,
+        receiver: Receiver<Event>,
+        finished: &AtomicBool,
+        quit: &AtomicBool,
+    ) -> Res<()> {
+        let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+
+        while !quit.load(Ordering::Relaxed) {
+            while let Ok(event) = receiver.try_recv() {
+                if let Some(line) = format_event(&event) {
+                    state.log.push(line);
+                }
+            }
+
+            if state.log.len() > MAX_LOG_LINES {
+                state.log.drain(..state.log.len() - MAX_LOG_LINES);
+            }
+
+            state.finished = finished.load(Ordering::Relaxed);
+
+            if !state.finished {
+                state.sample();
+            }
+
+            terminal.draw(|frame| state.render(frame))?;
+
+            if !event::poll(FRAME_INTERVAL)? {
+                continue;
+            }
+
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+
+                match key.code {
+                    KeyCode::Char('p') => state.stats.pause(),
+                    KeyCode::Char('r') => state.stats.resume(),
+                    KeyCode::Char('c') if !state.finished => {
+                        state.stats.cancel();
+                        state.log.push(String::from("cancelling..."));
+                    }
+                    KeyCode::Char('q') if state.finished => break,
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the final state on screen until the user quits
+    pub fn finish(mut self) {
+        self.finished.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        output::detach();
+        self.quit.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_show_the_two_largest_units() {
+        let cases = [
+            (0, "0m 0s"),
+            (59, "0m 59s"),
+            (61, "1m 1s"),
+            (3599, "59m 59s"),
+            (3600, "1h 0m"),
+            (86399, "23h 59m"),
+            (90000, "1d 1h"),
+        ];
+
+        for (secs, expected) in cases {
+            assert_eq!(format_duration(Duration::from_secs(secs)), expected);
+        }
+    }
+
+    #[test]
+    fn logs_only_job_milestones() {
+        let broadcast = Event::Broadcast {
+            success: false,
+            response: String::from("rejected"),
+            path: None,
+        };
+        assert_eq!(
+            format_event(&broadcast).as_deref(),
+            Some("broadcast failed rejected")
+        );
+
+        let saved = Event::Saved {
+            path: String::from("solutions/a.hex"),
+        };
+        assert_eq!(
+            format_event(&saved).as_deref(),
+            Some("saved to solutions/a.hex")
+        );
+
+        let error = Event::Error {
+            message: String::from("boom"),
+        };
+        assert_eq!(format_event(&error).as_deref(), Some("error boom"));
+
+        assert_eq!(format_event(&Event::Threads { threads: 4 }), None);
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{MagicMiner, MinerError, Res};
+use bsv::{P2PKHAddress, PublicKey, Script};
+
+/// Where a payout goes. Written in config and prompts as a plain address, handle or
+/// Paymail, or with a prefix: `p2pk:<pubkey>`, `multisig:<m>:<pubkey>,<pubkey>,...`,
+/// `hex:<script>`, `asm:<script>` or `21e8:<sha256>:<21e8 target>`.
+#[derive(Debug, Clone)]
+pub enum Destination {
+    /// P2PKH address, or a handle or Paymail that resolves to one
+    Address(String),
+    PubKey(String),
+    Multisig {
+        threshold: usize,
+        pubkeys: Vec<String>,
+    },
+    Script(Script),
+    /// Another 21e8 puzzle, for chained work
+    Puzzle {
+        hash: Vec<u8>,
+        target: Vec<u8>,
+    },
+}
+
+impl Destination {
+    pub fn parse(input: &str) -> Res<Destination> {
+        let input = input.trim();
+
+        let (kind, rest) = match input.split_once(':') {
+            Some((kind, rest)) => (kind.to_lowercase(), rest.trim()),
+            None => return Ok(Destination::Address(input.to_string())),
+        };
+
+        match kind.as_str() {
+            "p2pk" => Ok(Destination::PubKey(rest.to_string())),
+            "multisig" => {
+                let (threshold, pubkeys) = rest.split_once(':').ok_or_else(|| {
+                    MinerError::Parse(String::from("multisig needs <m>:<pubkey>,<pubkey>,..."))
+                })?;
+
+                Ok(Destination::Multisig {
+                    threshold: threshold.trim().parse()?,
+                    pubkeys: pubkeys.split(',').map(|k| k.trim().to_string()).collect(),
+                })
+            }
+            "hex" => Ok(Destination::Script(Script::from_hex(rest)?)),
+            "asm" => Ok(Destination::Script(Script::from_asm_string(rest)?)),
+            "21e8" => {
+                let (hash, target) = rest.split_once(':').ok_or_else(|| {
+                    MinerError::Parse(String::from("21e8 needs <sha256>:<21e8 target>"))
+                })?;
+
+                Ok(Destination::Puzzle {
+                    hash: hex::decode(hash.trim())?,
+                    target: hex::decode(target.trim())?,
+                })
+            }
+            // anything else, like a Paymail alias, is left for address resolution
+            _ => Ok(Destination::Address(input.to_string())),
+        }
+    }
+
+    /// Builds the locking script, `None` for addresses that still need resolving
+    pub fn script(&self) -> Option<Res<Script>> {
+        match self {
+            Destination::Address(address) => P2PKHAddress::from_string(address)
+                .ok()
+                .map(|address| Ok(address.get_locking_script()?)),
+            Destination::PubKey(pubkey) => Some(Destination::p2pk_script(pubkey)),
+            Destination::Multisig { threshold, pubkeys } => {
+                Some(Destination::multisig_script(*threshold, pubkeys))
+            }
+            Destination::Script(script) => Some(Ok(script.clone())),
+            Destination::Puzzle { hash, target } => Some(MagicMiner::puzzle_script(hash, target)),
+        }
+    }
+
+    fn p2pk_script(pubkey: &str) -> Res<Script> {
+        PublicKey::from_hex(pubkey)?;
+        Ok(Script::from_asm_string(&format!("{} OP_CHECKSIG", pubkey))?)
+    }
+
+    fn multisig_script(threshold: usize, pubkeys: &[String]) -> Res<Script> {
+        if threshold == 0 || threshold > pubkeys.len() || pubkeys.len() > 16 {
+            return Err(MinerError::Parse(format!(
+                "invalid {} of {} multisig",
+                threshold,
+                pubkeys.len()
+            )));
+        }
+
+        for pubkey in pubkeys {
+            PublicKey::from_hex(pubkey)?;
+        }
+
+        Ok(Script::from_asm_string(&format!(
+            "OP_{} {} OP_{} OP_CHECKMULTISIG",
+            threshold,
+            pubkeys.join(" "),
+            pubkeys.len()
+        ))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // secp256k1 G and 2G
+    const PUBKEY_1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const PUBKEY_2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    const ADDRESS: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+    const ADDRESS_SCRIPT: &str = "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac";
+
+    fn script_hex(input: &str) -> String {
+        hex::encode(
+            Destination::parse(input)
+                .unwrap()
+                .script()
+                .unwrap()
+                .unwrap()
+                .to_bytes(),
+        )
+    }
+
+    #[test]
+    fn addresses_and_handles() {
+        assert!(matches!(
+            Destination::parse(&format!(" {} ", ADDRESS)).unwrap(),
+            Destination::Address(address) if address == ADDRESS
+        ));
+        assert_eq!(script_hex(ADDRESS), ADDRESS_SCRIPT);
+
+        // left for Paymail resolution
+        for input in [
+            "alice@example.com",
+            "$alice",
+            "1alice",
+            "alias:with@colon.com",
+        ] {
+            let destination = Destination::parse(input).unwrap();
+            assert!(matches!(destination, Destination::Address(_)), "{}", input);
+            assert!(destination.script().is_none(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn p2pk() {
+        let input = format!("p2pk:{}", PUBKEY_1);
+
+        assert!(matches!(
+            Destination::parse(&input).unwrap(),
+            Destination::PubKey(pubkey) if pubkey == PUBKEY_1
+        ));
+        assert_eq!(script_hex(&input), format!("21{}ac", PUBKEY_1));
+        assert_eq!(
+            script_hex(&format!("P2PK: {}", PUBKEY_1)),
+            format!("21{}ac", PUBKEY_1)
+        );
+
+        for pubkey in ["", "02abcd", &PUBKEY_1[2..], "zz"] {
+            let destination = Destination::parse(&format!("p2pk:{}", pubkey)).unwrap();
+            assert!(destination.script().unwrap().is_err(), "{}", pubkey);
+        }
+    }
+
+    #[test]
+    fn multisig() {
+        let input = format!("multisig:1:{}, {}", PUBKEY_1, PUBKEY_2);
+
+        match Destination::parse(&input).unwrap() {
+            Destination::Multisig { threshold, pubkeys } => {
+                assert_eq!(threshold, 1);
+                assert_eq!(pubkeys, vec![PUBKEY_1, PUBKEY_2]);
+            }
+            other => panic!("unexpected destination {:?}", other),
+        }
+
+        assert_eq!(
+            script_hex(&input),
+            format!("5121{}21{}52ae", PUBKEY_1, PUBKEY_2)
+        );
+        assert_eq!(
+            script_hex(&format!("multisig:2:{},{}", PUBKEY_1, PUBKEY_2)),
+            format!("5221{}21{}52ae", PUBKEY_1, PUBKEY_2)
+        );
+
+        // invalid thresholds and keys only fail once the script is built
+        for input in [
+            format!("multisig:0:{},{}", PUBKEY_1, PUBKEY_2),
+            format!("multisig:3:{},{}", PUBKEY_1, PUBKEY_2),
+            format!("multisig:1:{},02abcd", PUBKEY_1),
+            format!("multisig:1:{}", vec![PUBKEY_1; 17].join(",")),
+        ] {
+            let destination = Destination::parse(&input).unwrap();
+            assert!(destination.script().unwrap().is_err(), "{}", input);
+        }
+
+        for input in [
+            format!("multisig:{}", PUBKEY_1),
+            format!("multisig:x:{}", PUBKEY_1),
+            format!("multisig:-1:{}", PUBKEY_1),
+        ] {
+            assert!(Destination::parse(&input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn raw_scripts() {
+        assert!(matches!(
+            Destination::parse(&format!("hex:{}", ADDRESS_SCRIPT)).unwrap(),
+            Destination::Script(_)
+        ));
+        assert_eq!(
+            script_hex(&format!("hex:{}", ADDRESS_SCRIPT)),
+            ADDRESS_SCRIPT
+        );
+        assert_eq!(script_hex("HEX:6a0221e8"), "6a0221e8");
+        assert_eq!(script_hex("asm:OP_RETURN 21e8"), "6a0221e8");
+        assert_eq!(
+            script_hex("asm:OP_DUP OP_HASH160 62e907b15cbf27d5425399ebf6f0fb50ebb88f18 OP_EQUALVERIFY OP_CHECKSIG"),
+            ADDRESS_SCRIPT
+        );
+
+        assert!(Destination::parse("hex:zz").is_err());
+        assert!(Destination::parse("hex:abc").is_err());
+        assert!(Destination::parse("asm:OP_NOT_AN_OPCODE").is_err());
+    }
+
+    #[test]
+    fn puzzles() {
+        let hash = "ab".repeat(32);
+        let input = format!("21e8:{}:21e8ff", hash);
+
+        match Destination::parse(&input).unwrap() {
+            Destination::Puzzle { hash: h, target } => {
+                assert_eq!(hex::encode(h), hash);
+                assert_eq!(target, vec![0x21, 0xe8, 0xff]);
+            }
+            other => panic!("unexpected destination {:?}", other),
+        }
+
+        let script = Destination::parse(&input)
+            .unwrap()
+            .script()
+            .unwrap()
+            .unwrap();
+
+        assert!(MagicMiner::is_21e8_out(&script).unwrap());
+        assert_eq!(
+            MagicMiner::puzzle_target(&script),
+            Some(vec![0x21, 0xe8, 0xff])
+        );
+
+        // a short hash or a target without the 21e8 prefix is not a puzzle
+        for input in [
+            String::from("21e8:abcd:21e8"),
+            format!("21e8:{}:ffff", hash),
+        ] {
+            let destination = Destination::parse(&input).unwrap();
+            assert!(destination.script().unwrap().is_err(), "{}", input);
+        }
+
+        assert!(Destination::parse(&format!("21e8:{}", hash)).is_err());
+        assert!(Destination::parse(&format!("21e8:{}:21e8zz", hash)).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use thiserror::Error;
+
+pub type Res<T> = Result<T, MinerError>;
+
+#[derive(Debug, Error)]
+pub enum MinerError {
+    #[error("network error: {0}")]
+    Network(String),
+    #[error("parse error: {0}")]
+    Parse(String),
+    #[error("config error: {0}")]
+    Config(String),
+    #[error("txid mismatch: requested {requested}, received {received}")]
+    TxidMismatch { requested: String, received: String },
+    #[error("merkle proof failed: {0}")]
+    MerkleProof(String),
+    #[error("script mismatch: {0}")]
+    ScriptMismatch(String),
+    #[error("insufficient value: {value} satoshis available, {required} required")]
+    InsufficientValue { value: u64, required: u64 },
+    #[error("signing error: {0}")]
+    Signing(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("worker {thread} failed: {cause}")]
+    Worker { thread: usize, cause: String },
+    #[error("job cancelled")]
+    Cancelled,
+}
+
+impl From<reqwest::Error> for MinerError {
+    fn from(e: reqwest::Error) -> Self {
+        MinerError::Network(e.to_string())
+    }
+}
+
+impl From<bsv::BSVErrors> for MinerError {
+    fn from(e: bsv::BSVErrors) -> Self {
+        MinerError::Parse(e.to_string())
+    }
+}
+
+impl From<hex::FromHexError> for MinerError {
+    fn from(e: hex::FromHexError) -> Self {
+        MinerError::Parse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for MinerError {
+    fn from(e: serde_json::Error) -> Self {
+        MinerError::Parse(e.to_string())
+    }
+}
+
+impl From<std::num::ParseIntError> for MinerError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        MinerError::Parse(e.to_string())
+    }
+}
+
+impl From<toml::de::Error> for MinerError {
+    fn from(e: toml::de::Error) -> Self {
+        MinerError::Config(e.to_string())
+    }
+}
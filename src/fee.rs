@@ -0,0 +1,157 @@
+use crate::{MagicMiner, Res};
+use bsv::{Script, ScriptBit, Transaction, TxIn};
+
+/// Default mining fee in satoshis per 1000 bytes
+pub const DEFAULT_FEE_RATE: u64 = 50;
+
+/// DER signatures are 70 to 72 bytes, plus the sighash flag
+const MAX_SIG_LEN: usize = 73;
+const PUBKEY_LEN: usize = 33;
+
+/// Fee for a transaction as it will look once every input is signed
+#[derive(Debug, Clone, Copy)]
+pub struct FeeBreakdown {
+    pub size: usize,
+    pub fee_rate: u64,
+    pub fee: u64,
+    pub value: u64,
+    pub payout: u64,
+}
+
+/// Satoshis for `size` bytes at `fee_rate` per 1000 bytes, rounded up
+pub fn fee_for_size(size: usize, fee_rate: u64) -> u64 {
+    (size as u64).saturating_mul(fee_rate).div_ceil(1000)
+}
+
+/// Stands in for the payout script when only its size matters
+pub fn placeholder_p2pkh() -> Res<Script> {
+    Ok(Script::from_bytes(&[0u8; 25])?)
+}
+
+impl FeeBreakdown {
+    /// Sizes `tx` with a worst-case signature and public key on every input
+    pub fn estimate(tx: &Transaction, value: u64, fee_rate: u64) -> Res<FeeBreakdown> {
+        let mut signed = tx.clone();
+
+        let mut unlocking_script = Script::default();
+        unlocking_script.push(ScriptBit::Push(vec![0u8; MAX_SIG_LEN]));
+        unlocking_script.push(ScriptBit::Push(vec![0u8; PUBKEY_LEN]));
+
+        for i in 0..signed.get_ninputs() {
+            if let Some(input) = signed.get_input(i) {
+                let placeholder = TxIn::new(
+                    &input.get_prev_tx_id(None),
+                    input.get_vout(),
+                    &unlocking_script,
+                    None,
+                );
+                signed.set_input(i, &placeholder);
+            }
+        }
+
+        let size = signed.to_bytes()?.len();
+        let fee = fee_for_size(size, fee_rate);
+        let payout = MagicMiner::check_value(value, fee)?;
+
+        Ok(FeeBreakdown {
+            size,
+            fee_rate,
+            fee,
+            value,
+            payout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MinerError;
+    use bsv::TxOut;
+
+    // version, input and output counts and locktime
+    const TX_OVERHEAD: usize = 4 + 1 + 1 + 4;
+    // outpoint, script length, two pushes and sequence
+    const SIGNED_INPUT: usize = 36 + 1 + (1 + MAX_SIG_LEN) + (1 + PUBKEY_LEN) + 4;
+    const P2PKH_OUTPUT: usize = 8 + 1 + 25;
+
+    fn unsigned_tx(inputs: usize) -> Transaction {
+        let mut tx = Transaction::new(1, 0);
+
+        for i in 0..inputs {
+            tx.add_input(&TxIn::new(&[i as u8; 32], 0, &Script::default(), None));
+        }
+
+        tx.add_output(&TxOut::new(0u64, &placeholder_p2pkh().unwrap()));
+        tx
+    }
+
+    #[test]
+    fn sizes_inputs_as_signed() {
+        let tx = unsigned_tx(1);
+        let unsigned_len = tx.to_bytes().unwrap().len();
+
+        let fees = FeeBreakdown::estimate(&tx, 1000, DEFAULT_FEE_RATE).unwrap();
+
+        assert_eq!(fees.size, TX_OVERHEAD + SIGNED_INPUT + P2PKH_OUTPUT);
+        assert_eq!(fees.size, 193);
+        assert_eq!(fees.fee, 10);
+        assert_eq!(fees.value, 1000);
+        assert_eq!(fees.payout, 990);
+        assert_eq!(fees.fee_rate, DEFAULT_FEE_RATE);
+
+        // the transaction itself is left unsigned
+        assert_eq!(tx.to_bytes().unwrap().len(), unsigned_len);
+
+        let fees = FeeBreakdown::estimate(&unsigned_tx(2), 1000, DEFAULT_FEE_RATE).unwrap();
+        assert_eq!(fees.size, 193 + SIGNED_INPUT);
+    }
+
+    #[test]
+    fn fee_rounds_up_to_whole_satoshis() {
+        let tx = unsigned_tx(1);
+
+        let cases = [
+            (0, 0),
+            (1, 1),
+            (5, 1),
+            (6, 2),
+            (50, 10),
+            (1000, 193),
+            (1001, 194),
+        ];
+
+        for (fee_rate, fee) in cases {
+            assert_eq!(
+                FeeBreakdown::estimate(&tx, 10_000, fee_rate).unwrap().fee,
+                fee,
+                "{} sat/kB",
+                fee_rate
+            );
+        }
+    }
+
+    #[test]
+    fn fee_for_size_rounds_up() {
+        assert_eq!(fee_for_size(0, 50), 0);
+        assert_eq!(fee_for_size(1, 1), 1);
+        assert_eq!(fee_for_size(1000, 1), 1);
+        assert_eq!(fee_for_size(1001, 1), 2);
+        assert_eq!(fee_for_size(193, 50), 10);
+        assert_eq!(fee_for_size(usize::MAX, u64::MAX), u64::MAX / 1000 + 1);
+    }
+
+    #[test]
+    fn refuses_values_below_fee_and_dust() {
+        let tx = unsigned_tx(1);
+
+        assert_eq!(FeeBreakdown::estimate(&tx, 11, 50).unwrap().payout, 1);
+
+        match FeeBreakdown::estimate(&tx, 10, 50) {
+            Err(MinerError::InsufficientValue { value, required }) => {
+                assert_eq!((value, required), (10, 11));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
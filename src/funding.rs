@@ -0,0 +1,392 @@
+use crate::{MinerError, Res};
+use bsv::{
+    ExtendedPrivateKey, P2PKHAddress, PrivateKey, Script, ScriptBit, SigHash, Transaction, TxIn,
+};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Funding outpoints spent by transactions that have not reached the explorer yet
+pub const FUNDING_INFLIGHT_PATH: &str = "funding_inflight.json";
+
+/// Seconds after which a reservation is dropped, e.g. when a job was cancelled
+pub const INFLIGHT_TTL: u64 = 60 * 60;
+
+#[derive(Deserialize)]
+struct Unspent {
+    tx_hash: String,
+    tx_pos: u32,
+    value: u64,
+}
+
+impl Unspent {
+    fn outpoint(&self) -> String {
+        format!("{}:{}", self.tx_hash.to_lowercase(), self.tx_pos)
+    }
+}
+
+/// Reservations of funding outpoints by the time they were picked, stored on disk like
+/// `TxCache` so jobs running in other processes skip them too
+pub struct InFlight {
+    path: PathBuf,
+}
+
+impl Default for InFlight {
+    fn default() -> Self {
+        InFlight::new(FUNDING_INFLIGHT_PATH)
+    }
+}
+
+impl InFlight {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        InFlight {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn load(&self) -> Res<BTreeMap<String, u64>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, reserved: &BTreeMap<String, u64>) -> Res<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(reserved)?)?;
+        std::fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    pub fn release(&self, outpoint: &str) -> Res<()> {
+        let mut reserved = self.load()?;
+
+        if reserved.remove(outpoint).is_some() {
+            self.save(&reserved)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Drops reservations that expired or whose outpoint is no longer unspent, then picks the
+/// largest outpoint that is not reserved
+fn select(
+    unspent: Vec<Unspent>,
+    reserved: &mut BTreeMap<String, u64>,
+    now: u64,
+) -> Option<Unspent> {
+    reserved.retain(|outpoint, at| {
+        now.saturating_sub(*at) < INFLIGHT_TTL && unspent.iter().any(|u| &u.outpoint() == outpoint)
+    });
+
+    unspent
+        .into_iter()
+        .filter(|utxo| !reserved.contains_key(&utxo.outpoint()))
+        .max_by_key(|utxo| utxo.value)
+}
+
+/// A wallet UTXO spent next to a puzzle to pay its fee, with change going back to the wallet
+pub struct Funding {
+    pub key: PrivateKey,
+    pub address: String,
+    pub script: Script,
+    /// Previous txid in display order
+    pub txid: Vec<u8>,
+    pub vout: u32,
+    pub satoshis: u64,
+}
+
+impl Funding {
+    /// Accepts a WIF or an xprv, whose own key is used
+    pub fn key(funding_key: &str) -> Res<PrivateKey> {
+        let funding_key = funding_key.trim();
+
+        match funding_key.starts_with("xprv") {
+            true => Ok(ExtendedPrivateKey::from_string(funding_key)?.get_private_key()),
+            false => Ok(PrivateKey::from_wif(funding_key)?),
+        }
+    }
+
+    fn from_key(key: PrivateKey, txid: Vec<u8>, vout: u32, satoshis: u64) -> Res<Funding> {
+        let address = P2PKHAddress::from_pubkey(&key.to_public_key()?)?;
+
+        Ok(Funding {
+            address: address.to_string()?,
+            script: address.get_locking_script()?,
+            key,
+            txid,
+            vout,
+            satoshis,
+        })
+    }
+
+    /// Picks the largest UTXO of the funding key that no other job has reserved.
+    ///
+    /// The explorer only drops an outpoint once the transaction spending it is seen, so the
+    /// pick is kept in `funding_inflight.json` until then, for at most `INFLIGHT_TTL`.
+    pub async fn fetch(funding_key: &str) -> Res<Funding> {
+        Funding::fetch_with(funding_key, &InFlight::default()).await
+    }
+
+    async fn fetch_with(funding_key: &str, in_flight: &InFlight) -> Res<Funding> {
+        let key = Funding::key(funding_key)?;
+        let address = P2PKHAddress::from_pubkey(&key.to_public_key()?)?.to_string()?;
+
+        let url = format!(
+            "https://api.whatsonchain.com/v1/bsv/main/address/{}/unspent",
+            address
+        );
+
+        let unspent = reqwest::Client::new()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<Unspent>>()
+            .await?;
+
+        let mut reserved = in_flight.load()?;
+
+        let utxo = select(unspent, &mut reserved, now()).ok_or_else(|| {
+            MinerError::Network(format!(
+                "no unreserved UTXOs for funding address {}",
+                address
+            ))
+        })?;
+
+        reserved.insert(utxo.outpoint(), now());
+        in_flight.save(&reserved)?;
+
+        tracing::info!(%address, txid = %utxo.tx_hash, vout = utxo.tx_pos, satoshis = utxo.value, "funding input selected");
+
+        Funding::from_key(key, hex::decode(&utxo.tx_hash)?, utxo.tx_pos, utxo.value)
+    }
+
+    /// `txid:vout` as reserved in `funding_inflight.json`
+    pub fn outpoint(&self) -> String {
+        format!("{}:{}", hex::encode(&self.txid), self.vout)
+    }
+
+    /// Frees the UTXO for other jobs when the transaction spending it was never sent
+    pub fn release(&self) -> Res<()> {
+        InFlight::default().release(&self.outpoint())
+    }
+
+    /// Stands in for a real UTXO when only the transaction size matters
+    pub fn placeholder() -> Res<Funding> {
+        Funding::from_key(PrivateKey::from_random(), vec![0u8; 32], 0, u64::MAX / 2)
+    }
+
+    pub fn tx_in(&self) -> TxIn {
+        let mut tx_in = TxIn::default();
+
+        tx_in.set_satoshis(self.satoshis);
+        tx_in.set_locking_script(&self.script);
+        tx_in.set_prev_tx_id(&self.txid);
+        tx_in.set_vout(self.vout);
+
+        tx_in
+    }
+
+    /// Adds a normal P2PKH signature to input `index`, once every output is final
+    pub fn sign(&self, tx: &mut Transaction, index: usize) -> Res<()> {
+        sign_p2pkh(tx, index, &self.key, self.satoshis)
+    }
+}
+
+/// Signs input `index` of `tx` as a P2PKH spend of `satoshis` locked to `key`'s address,
+/// once every output is final
+pub fn sign_p2pkh(tx: &mut Transaction, index: usize, key: &PrivateKey, satoshis: u64) -> Res<()> {
+    let input = tx
+        .get_input(index)
+        .ok_or_else(|| MinerError::Signing(format!("transaction has no input {}", index)))?;
+
+    let public_key = key.to_public_key()?;
+    let locking_script = P2PKHAddress::from_pubkey(&public_key)?.get_locking_script()?;
+
+    let sig = tx
+        .sign(
+            key,
+            SigHash::InputsOutputs,
+            index,
+            &locking_script,
+            satoshis,
+        )
+        .map_err(|e| MinerError::Signing(e.to_string()))?;
+
+    let mut unlocking_script = Script::default();
+
+    unlocking_script.push(ScriptBit::Push(sig.to_bytes()?));
+    unlocking_script.push(ScriptBit::Push(public_key.to_bytes()?));
+
+    tx.set_input(
+        index,
+        &TxIn::new(
+            &input.get_prev_tx_id(None),
+            input.get_vout(),
+            &unlocking_script,
+            None,
+        ),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098";
+
+    fn unspent(tx_pos: u32, value: u64) -> Unspent {
+        Unspent {
+            tx_hash: TXID.to_uppercase(),
+            tx_pos,
+            value,
+        }
+    }
+
+    #[test]
+    fn outpoints_are_lowercase_txid_and_vout() {
+        assert_eq!(unspent(3, 1).outpoint(), format!("{}:3", TXID));
+    }
+
+    #[test]
+    fn picks_the_largest_unreserved_utxo() {
+        let mut reserved = BTreeMap::new();
+
+        let utxo = select(vec![unspent(0, 500), unspent(1, 900)], &mut reserved, 1_000).unwrap();
+        assert_eq!(utxo.tx_pos, 1);
+
+        reserved.insert(unspent(1, 0).outpoint(), 1_000);
+
+        let utxo = select(vec![unspent(0, 500), unspent(1, 900)], &mut reserved, 1_010).unwrap();
+        assert_eq!(utxo.tx_pos, 0);
+
+        reserved.insert(unspent(0, 0).outpoint(), 1_010);
+        assert!(select(vec![unspent(0, 500), unspent(1, 900)], &mut reserved, 1_020).is_none());
+    }
+
+    #[test]
+    fn drops_expired_and_spent_reservations() {
+        let mut reserved = BTreeMap::new();
+        reserved.insert(unspent(0, 0).outpoint(), 1_000);
+        reserved.insert(unspent(1, 0).outpoint(), 1_000 + INFLIGHT_TTL);
+        reserved.insert(unspent(2, 0).outpoint(), 1_000 + INFLIGHT_TTL);
+
+        let now = 1_000 + INFLIGHT_TTL;
+        let utxo = select(vec![unspent(0, 100), unspent(1, 900)], &mut reserved, now).unwrap();
+
+        // vout 0 expired, vout 2 was spent, vout 1 is still in flight
+        assert_eq!(utxo.tx_pos, 0);
+        assert_eq!(
+            reserved.keys().cloned().collect::<Vec<_>>(),
+            vec![unspent(1, 0).outpoint()]
+        );
+    }
+
+    #[test]
+    fn reservations_round_trip_on_disk() {
+        let path = std::env::temp_dir().join("magic_miner_funding_inflight.json");
+        let _ = std::fs::remove_file(&path);
+        let in_flight = InFlight::new(&path);
+
+        assert!(in_flight.load().unwrap().is_empty());
+
+        let mut reserved = BTreeMap::new();
+        reserved.insert(unspent(1, 0).outpoint(), 42);
+        in_flight.save(&reserved).unwrap();
+
+        assert_eq!(in_flight.load().unwrap(), reserved);
+
+        in_flight.release("unknown:0").unwrap();
+        assert_eq!(in_flight.load().unwrap(), reserved);
+
+        in_flight.release(&unspent(1, 0).outpoint()).unwrap();
+        assert!(in_flight.load().unwrap().is_empty());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn accepts_wif_and_xprv_keys() {
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        let xprv = "xprv9s21ZrQH143K31xYSDQpPDxsXRTUcvj2iNHm5NUtrGiGG5e2DtALGdso3pGz6ssrdK4PFmM8NSpSBHNqPqm55Qn3LqFtT2emdEXVYsCzC2U";
+
+        assert!(Funding::key(wif).is_ok());
+        assert!(Funding::key(&format!(" {} ", xprv)).is_ok());
+        assert!(Funding::key("not a key").is_err());
+    }
+
+    #[test]
+    fn signs_inputs_as_p2pkh_spends() {
+        let funding = Funding::from_key(
+            PrivateKey::from_random(),
+            hex::decode(TXID).unwrap(),
+            3,
+            5_000,
+        )
+        .unwrap();
+
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&funding.tx_in());
+        tx.add_output(&bsv::TxOut::new(4_000u64, &funding.script));
+
+        funding.sign(&mut tx, 0).unwrap();
+
+        let input = tx.get_input(0).unwrap();
+        assert_eq!(input.get_prev_tx_id(None), hex::decode(TXID).unwrap());
+        assert_eq!(input.get_vout(), 3);
+
+        // <sig> <pubkey>
+        let unlocking_script = input.get_unlocking_script();
+        assert_eq!(
+            unlocking_script
+                .get_script_bit(1)
+                .and_then(|bit| bit.to_vec()),
+            Some(funding.key.to_public_key().unwrap().to_bytes().unwrap())
+        );
+
+        assert!(matches!(
+            sign_p2pkh(&mut tx, 1, &funding.key, 5_000),
+            Err(MinerError::Signing(_))
+        ));
+    }
+
+    #[test]
+    fn tx_in_spends_the_funding_outpoint() {
+        let funding = Funding::from_key(
+            PrivateKey::from_random(),
+            hex::decode(TXID).unwrap(),
+            2,
+            5_000,
+        )
+        .unwrap();
+        let tx_in = funding.tx_in();
+
+        assert_eq!(tx_in.get_prev_tx_id(None), hex::decode(TXID).unwrap());
+        assert_eq!(tx_in.get_vout(), 2);
+        assert_eq!(tx_in.get_satoshis(), Some(5_000));
+        assert_eq!(
+            tx_in.get_locking_script().map(|s| s.to_bytes()),
+            Some(funding.script.to_bytes())
+        );
+
+        // released under the same key it was reserved with
+        assert_eq!(funding.outpoint(), unspent(2, 0).outpoint());
+
+        let placeholder = Funding::placeholder().unwrap();
+        assert_eq!(placeholder.txid, vec![0u8; 32]);
+        assert!(placeholder.address.starts_with('1'));
+    }
+}
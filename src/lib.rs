@@ -1,3 +1,6 @@
+pub mod error;
+pub use error::*;
+
 pub mod miner;
 pub use miner::*;
 
@@ -12,3 +15,51 @@ pub use cli::*;
 
 pub mod prompt;
 pub use prompt::*;
+
+pub mod spv;
+pub use spv::*;
+
+pub mod cache;
+pub use cache::*;
+
+pub mod output;
+pub use output::*;
+
+pub mod stats;
+pub use stats::*;
+
+pub mod dashboard;
+pub use dashboard::*;
+
+pub mod logging;
+pub use logging::*;
+
+pub mod metrics;
+pub use metrics::*;
+
+pub mod fee;
+pub use fee::*;
+
+pub mod payout;
+pub use payout::*;
+
+pub mod destination;
+pub use destination::*;
+
+pub mod paymail;
+pub use paymail::*;
+
+pub mod xpub;
+pub use xpub::*;
+
+pub mod funding;
+pub use funding::*;
+
+pub mod cpfp;
+pub use cpfp::*;
+
+pub mod wallet;
+pub use wallet::*;
+
+pub mod publisher;
+pub use publisher::*;
@@ -0,0 +1,74 @@
+use crate::{MinerError, Res};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// Maps `-v` occurrences to a default filter, `RUST_LOG` always takes precedence
+fn default_filter(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 => "warn",
+        1 => "magic_miner=info,warn",
+        2 => "magic_miner=debug,info",
+        _ => "trace",
+    }
+}
+
+fn env_filter(verbosity: u8) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter(verbosity)))
+}
+
+/// Installs the global subscriber. Logs go to stderr unless `to_stderr` is false,
+/// and to a daily rotating `miner.log` in `log_dir` when one is given.
+/// The returned guard flushes the file writer and must be kept alive.
+pub fn init(verbosity: u8, to_stderr: bool, log_dir: Option<&str>) -> Res<Option<WorkerGuard>> {
+    let stderr_layer = match to_stderr {
+        true => Some(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(env_filter(verbosity)),
+        ),
+        false => None,
+    };
+
+    let (file_layer, guard) = match log_dir {
+        Some(dir) => {
+            let appender = tracing_appender::rolling::daily(dir, "miner.log");
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+
+            let layer = fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer)
+                .with_filter(env_filter(verbosity.max(1)));
+
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(stderr_layer)
+        .with(file_layer)
+        .try_init()
+        .map_err(|e| MinerError::Config(e.to_string()))?;
+
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verbosity_widens_the_default_filter() {
+        assert_eq!(default_filter(0), "warn");
+        assert_eq!(default_filter(1), "magic_miner=info,warn");
+        assert_eq!(default_filter(2), "magic_miner=debug,info");
+        assert_eq!(default_filter(3), "trace");
+        assert_eq!(default_filter(u8::MAX), "trace");
+
+        for verbosity in 0..4 {
+            assert!(default_filter(verbosity).parse::<EnvFilter>().is_ok());
+        }
+    }
+}
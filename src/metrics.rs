@@ -0,0 +1,247 @@
+use crate::stats::MiningStats;
+use crate::Res;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Process-wide counters exported in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    jobs_started: AtomicU64,
+    jobs_solved: AtomicU64,
+    jobs_abandoned: AtomicU64,
+    finished_attempts: AtomicU64,
+    satoshis_earned: AtomicU64,
+    target_difficulty: AtomicU64,
+    broadcasts: Mutex<BTreeMap<(String, bool), u64>>,
+    current_job: Mutex<Option<Arc<MiningStats>>>,
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    pub fn job_started(&self, stats: Arc<MiningStats>) {
+        self.jobs_started.fetch_add(1, Ordering::Relaxed);
+        self.target_difficulty
+            .store(stats.expected_attempts().to_bits(), Ordering::Relaxed);
+
+        if let Ok(mut current) = self.current_job.lock() {
+            *current = Some(stats);
+        }
+    }
+
+    pub fn job_finished(&self, solved: bool) {
+        let stats = self.current_job.lock().ok().and_then(|mut c| c.take());
+
+        if let Some(stats) = stats {
+            self.finished_attempts
+                .fetch_add(stats.attempts(), Ordering::Relaxed);
+        }
+
+        match solved {
+            true => self.jobs_solved.fetch_add(1, Ordering::Relaxed),
+            false => self.jobs_abandoned.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn broadcast(&self, endpoint: &str, success: bool) {
+        if let Ok(mut broadcasts) = self.broadcasts.lock() {
+            *broadcasts
+                .entry((endpoint.to_string(), success))
+                .or_insert(0) += 1;
+        }
+    }
+
+    pub fn earned(&self, satoshis: u64) {
+        self.satoshis_earned.fetch_add(satoshis, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let current = self.current_job.lock().ok().and_then(|c| c.clone());
+        let current_attempts = current.as_ref().map(|s| s.attempts()).unwrap_or(0);
+
+        let mut out = String::new();
+
+        let counters = [
+            (
+                "magic_miner_attempts_total",
+                "Signatures hashed",
+                self.finished_attempts.load(Ordering::Relaxed) + current_attempts,
+            ),
+            (
+                "magic_miner_jobs_started_total",
+                "Mining jobs started",
+                self.jobs_started.load(Ordering::Relaxed),
+            ),
+            (
+                "magic_miner_jobs_solved_total",
+                "Mining jobs solved",
+                self.jobs_solved.load(Ordering::Relaxed),
+            ),
+            (
+                "magic_miner_jobs_abandoned_total",
+                "Mining jobs cancelled or failed",
+                self.jobs_abandoned.load(Ordering::Relaxed),
+            ),
+            (
+                "magic_miner_satoshis_earned_total",
+                "Satoshis paid out by broadcast solutions",
+                self.satoshis_earned.load(Ordering::Relaxed),
+            ),
+        ];
+
+        for (name, help, value) in counters {
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}"
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP magic_miner_broadcasts_total Broadcast attempts by endpoint\n# TYPE magic_miner_broadcasts_total counter"
+        );
+
+        if let Ok(broadcasts) = self.broadcasts.lock() {
+            for ((endpoint, success), count) in broadcasts.iter() {
+                let result = match success {
+                    true => "success",
+                    false => "failure",
+                };
+
+                let _ = writeln!(
+                    out,
+                    "magic_miner_broadcasts_total{{endpoint=\"{endpoint}\",result=\"{result}\"}} {count}"
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP magic_miner_target_difficulty Expected attempts for the current target\n# TYPE magic_miner_target_difficulty gauge\nmagic_miner_target_difficulty {}",
+            f64::from_bits(self.target_difficulty.load(Ordering::Relaxed))
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP magic_miner_hashrate Average hashes per second of the current job\n# TYPE magic_miner_hashrate gauge"
+        );
+
+        if let Some(stats) = current {
+            let secs = stats.elapsed().as_secs_f64().max(f64::EPSILON);
+
+            for (thread, attempts) in stats.thread_attempts().iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "magic_miner_hashrate{{thread=\"{thread}\"}} {}",
+                    *attempts as f64 / secs
+                );
+            }
+        }
+
+        out
+    }
+}
+
+/// Serves `/metrics` on `addr` until the process exits
+pub async fn serve(addr: &str) -> Res<()> {
+    let listener = TcpListener::bind(addr).await?;
+
+    tracing::info!(%addr, "metrics endpoint listening");
+
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    tracing::warn!(error = %e, "metrics accept failed");
+                    continue;
+                }
+            };
+
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+
+                let response = match request.starts_with("GET /metrics") {
+                    true => {
+                        let body = metrics().render();
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    false => String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    ),
+                };
+
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_broadcasts() {
+        let metrics = Metrics::default();
+
+        metrics.broadcast("whatsonchain", true);
+        metrics.broadcast("whatsonchain", true);
+        metrics.broadcast("arc", false);
+        metrics.earned(1500);
+        metrics.job_finished(true);
+        metrics.job_finished(false);
+
+        let out = metrics.render();
+
+        assert!(out.contains("# TYPE magic_miner_attempts_total counter\n"));
+        assert!(out.contains("magic_miner_jobs_solved_total 1\n"));
+        assert!(out.contains("magic_miner_jobs_abandoned_total 1\n"));
+        assert!(out.contains("magic_miner_satoshis_earned_total 1500\n"));
+        assert!(out.contains(
+            "magic_miner_broadcasts_total{endpoint=\"whatsonchain\",result=\"success\"} 2\n"
+        ));
+        assert!(
+            out.contains("magic_miner_broadcasts_total{endpoint=\"arc\",result=\"failure\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn current_job_attempts_are_counted_until_finished() {
+        let metrics = Metrics::default();
+        let stats = Arc::new(MiningStats::new(2, &[0x21, 0xe8]));
+
+        metrics.job_started(stats.clone());
+
+        stats.record(0, &[0x00], &[0x21, 0xe8]);
+        stats.record(1, &[0x00], &[0x21, 0xe8]);
+        stats.record(1, &[0x00], &[0x21, 0xe8]);
+
+        let out = metrics.render();
+        assert!(out.contains("magic_miner_attempts_total 3\n"));
+        assert!(out.contains("magic_miner_jobs_started_total 1\n"));
+        assert!(out.contains("magic_miner_target_difficulty 65536\n"));
+        assert!(out.contains("magic_miner_hashrate{thread=\"1\"}"));
+
+        metrics.job_finished(true);
+
+        let out = metrics.render();
+        assert!(out.contains("magic_miner_attempts_total 3\n"));
+        assert!(!out.contains("magic_miner_hashrate{thread="));
+    }
+}
@@ -1,40 +1,85 @@
+use crate::cache::TxCache;
+use crate::dashboard::{Dashboard, DashboardJob};
+use crate::destination::Destination;
+use crate::fee::{self, FeeBreakdown};
+use crate::funding::Funding;
+use crate::metrics::metrics;
+use crate::output::{self, Event, OutputMode, GREEN, RED, RESET_COLOR};
+use crate::paymail::PaymailClient;
+use crate::payout::{Payout, PayoutRule, Share};
 use crate::prompt::Prompt;
+use crate::spv::{HeaderStore, MerkleProof};
+use crate::stats::{JobSummary, LifetimeStats, MiningStats};
 use crate::utils;
+use crate::xpub::XpubWallet;
 use crate::Config;
-use asky::Text;
+use crate::{MinerError, Res};
+use asky::{Select, Text};
 use bsv::{
-    Hash, MatchToken, OpCodes, P2PKHAddress, PrivateKey, Script, ScriptBit, ScriptTemplate,
-    SigHash, SighashSignature, Transaction, TxIn, TxOut, ECDSA,
+    Hash, MatchToken, OpCodes, P2PKHAddress, PrivateKey, PublicKey, Script, ScriptBit,
+    ScriptTemplate, SigHash, SighashSignature, Signature, Transaction, TxIn, TxOut, ECDSA,
 };
-use serde_json::json;
+use serde_json::{json, Value};
+use std::any::Any;
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{RecvTimeoutError, Sender};
 use std::sync::Arc;
-use std::time::Duration;
-
-pub type Res<T> = anyhow::Result<T>;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 pub struct MagicMiner;
 
 pub struct MinerResult(SighashSignature, PrivateKey);
 
-// ANSI escape
-const RED: &str = "\x1b[31m";
-const GREEN: &str = "\x1b[32m";
-const YELLOW: &str = "\x1b[33m";
-const PURPLE: &str = "\x1b[35m";
-const CYAN: &str = "\x1b[36m";
-const RESET_COLOR: &str = "\x1B[0m";
+/// How often `mine_target` reports progress
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Longer targets can never match a 32-byte signature hash
+const MAX_TARGET_LEN: usize = 32;
+
+/// Smallest output value the network relays
+pub const DUST_LIMIT: u64 = 1;
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("unknown panic"),
+        },
+    }
+}
+
+/// Stops every worker and waits for it, reporting the first one that died
+fn stop_workers(stop_signal: &AtomicBool, handles: Vec<JoinHandle<()>>) -> Res<()> {
+    stop_signal.store(true, Ordering::Relaxed);
+
+    let mut result = Ok(());
+
+    for (thread, handle) in handles.into_iter().enumerate() {
+        if let Err(payload) = handle.join() {
+            if result.is_ok() {
+                result = Err(MinerError::Worker {
+                    thread,
+                    cause: panic_message(payload),
+                });
+            }
+        }
+    }
+
+    result
+}
 
 #[cfg_attr(docsrs, doc(cfg(feature = "miner")))]
 impl MagicMiner {
+    /// True for a pushed target starting with `21e8` that a SHA-256 hash can still match
     pub fn is_21e8(target_bit: ScriptBit) -> bool {
-        if let Some(target) = target_bit.to_vec() {
-            target[0] == 33 && target[1] == 232
-        } else {
-            false
+        match target_bit.to_vec() {
+            Some(target) => target.len() <= MAX_TARGET_LEN && target.starts_with(&[0x21, 0xe8]),
+            None => false,
         }
     }
 
@@ -52,45 +97,204 @@ impl MagicMiner {
             MatchToken::OpCode(OpCodes::OP_EQUALVERIFY),
             MatchToken::OpCode(OpCodes::OP_DROP),
             MatchToken::OpCode(OpCodes::OP_CHECKSIG),
-        ])?;
+        ])
+        .map_err(|e| MinerError::ScriptMismatch(e.to_string()))?;
+
+        // cheap structural checks first, so odd scripts never reach the template matcher
+        let hash = match script.get_script_bit(0).and_then(|bit| bit.to_vec()) {
+            Some(hash) => hash,
+            None => return Ok(false),
+        };
+
+        let target = match script.get_script_bit(1) {
+            Some(target) => target,
+            None => return Ok(false),
+        };
 
-        let target = script.get_script_bit(1).unwrap();
+        if hash.len() != 32 || !MagicMiner::is_21e8(target) {
+            return Ok(false);
+        }
+
+        Ok(script.is_match(&script_template))
+    }
 
-        Ok(script.is_match(&script_template) && MagicMiner::is_21e8(target))
+    /// Builds the 21e8 locking script for `hash`, with `target` including its `21e8` prefix
+    pub fn puzzle_script(hash: &[u8], target: &[u8]) -> Res<Script> {
+        let script = Script::from_asm_string(&format!(
+            "{} {} OP_SIZE OP_4 OP_PICK OP_SHA256 OP_SWAP OP_SPLIT OP_DROP OP_EQUALVERIFY OP_DROP OP_CHECKSIG",
+            hex::encode(hash),
+            hex::encode(target)
+        ))?;
+
+        match MagicMiner::is_21e8_out(&script)? {
+            true => Ok(script),
+            false => Err(MinerError::ScriptMismatch(format!(
+                "{} is not a 32-byte hash with a 21e8 target",
+                hex::encode(hash)
+            ))),
+        }
     }
 
     pub async fn get_tx(txid: &str) -> Res<Transaction> {
+        let cache = TxCache::default();
+
+        if let Some(tx) = cache.get(txid) {
+            tracing::debug!(txid, "loaded transaction from cache");
+            return Ok(tx);
+        }
+
+        tracing::debug!(txid, "fetching transaction");
+
         let url = format!("https://api.whatsonchain.com/v1/bsv/main/tx/{}/hex", txid);
-        let tx_hex = reqwest::Client::new().get(url).send().await?.text().await?;
-        Ok(Transaction::from_hex(&tx_hex)?)
+        let response = reqwest::Client::new().get(url).send().await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(MinerError::Parse(format!("transaction {} not found", txid)));
+        }
+
+        let tx_hex = response.error_for_status()?.text().await?;
+        let tx = Transaction::from_hex(tx_hex.trim())?;
+
+        let fetched_txid = tx.get_id_hex()?;
+
+        if !fetched_txid.eq_ignore_ascii_case(txid) {
+            return Err(MinerError::TxidMismatch {
+                requested: txid.to_string(),
+                received: fetched_txid,
+            });
+        }
+
+        // the cache only saves a request next time, so a failed write is not fatal
+        if let Err(e) = cache.put(&tx) {
+            tracing::warn!(txid, error = %e, "could not cache transaction");
+        }
+
+        Ok(tx)
+    }
+
+    /// Checks that the transaction is included in a block we know about
+    pub async fn verify_inclusion(txid: &str, headers_path: &str) -> Res<()> {
+        let headers = HeaderStore::from_file(headers_path)?;
+        let proof = MerkleProof::fetch(txid).await?;
+        proof.verify(txid, &headers)?;
+
+        tracing::info!(txid, block = %proof.target, "merkle proof verified");
+
+        Ok(())
     }
 
     pub async fn broadcast_tx(tx: &str) -> Res<String> {
         let mut map = HashMap::new();
         map.insert("txhex", tx);
 
-        Ok(reqwest::Client::new()
+        let response = reqwest::Client::new()
             .post("https://api.whatsonchain.com/v1/bsv/main/tx/raw")
             .json(&map)
             .send()
-            .await?
-            .text()
-            .await?)
+            .await;
+
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => {
+                metrics().broadcast("whatsonchain", false);
+                return Err(e.into());
+            }
+        };
+
+        let status = response.status();
+        let body = response.text().await?;
+
+        metrics().broadcast("whatsonchain", status.is_success());
+
+        if !status.is_success() {
+            tracing::warn!(%status, response = %body, "broadcast rejected");
+            return Err(MinerError::Network(format!(
+                "broadcast failed ({}): {}",
+                status, body
+            )));
+        }
+
+        tracing::info!(response = %body.trim(), "broadcast accepted");
+
+        Ok(body)
+    }
+
+    /// Checks that input 0 of `tx` is a valid proof of work for the 21e8 output it spends
+    pub fn verify_solution(tx: &Transaction, source: &Transaction) -> Res<bool> {
+        let input = match tx.get_input(0) {
+            Some(input) => input,
+            None => return Ok(false),
+        };
+
+        let source_output = match source.get_output(input.get_vout() as usize) {
+            Some(output) => output,
+            None => return Ok(false),
+        };
+
+        let locking_script = source_output.get_script_pub_key();
+
+        if !MagicMiner::is_21e8_out(&locking_script)? {
+            return Ok(false);
+        }
+
+        let target = match MagicMiner::puzzle_target(&locking_script) {
+            Some(target) => target,
+            None => return Ok(false),
+        };
+
+        let unlocking_script = input.get_unlocking_script();
+
+        let (sig_bytes, pub_key_bytes) = match (
+            unlocking_script.get_script_bit(0).and_then(|b| b.to_vec()),
+            unlocking_script.get_script_bit(1).and_then(|b| b.to_vec()),
+        ) {
+            (Some(sig), Some(pub_key)) if sig.len() > 1 => (sig, pub_key),
+            _ => return Ok(false),
+        };
+
+        if !Hash::sha_256(&sig_bytes).to_bytes().starts_with(&target) {
+            return Ok(false);
+        }
+
+        let sig_hash_preimage = tx.sighash_preimage(
+            SigHash::InputsOutputs,
+            0,
+            &locking_script,
+            source_output.get_satoshis(),
+        )?;
+
+        let signature = Signature::from_der(&sig_bytes[..sig_bytes.len() - 1])?;
+        let public_key = PublicKey::from_bytes(&pub_key_bytes)?;
+
+        Ok(ECDSA::verify_digest(
+            &sig_hash_preimage,
+            &public_key,
+            &signature,
+            bsv::SigningHash::Sha256d,
+        )?)
     }
 
     /// 🪄
     pub fn sign(
         sender: Sender<MinerResult>,
         stop_signal: &AtomicBool,
+        stats: &MiningStats,
+        thread: usize,
         sig_hash_preimage: Arc<Vec<u8>>,
         target: Arc<Vec<u8>>,
-    ) {
+    ) -> Res<()> {
         let preimage_ref: Vec<u8> = sig_hash_preimage.iter().cloned().collect();
         let target_ref: Vec<u8> = target.iter().cloned().collect();
+        let print_progress = output::is_text();
 
         loop {
             if stop_signal.load(Ordering::Relaxed) {
-                return;
+                return Ok(());
+            }
+
+            if stats.is_paused() {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
             }
 
             let ephemeral_key = PrivateKey::from_random();
@@ -101,37 +305,65 @@ impl MagicMiner {
                 bsv::SigningHash::Sha256d,
                 false,
             )
-            .unwrap();
+            .map_err(|e| MinerError::Signing(e.to_string()))?;
 
             let sighash_signature =
                 SighashSignature::new(&sig, SigHash::InputsOutputs, &preimage_ref);
 
-            let sig256 = Hash::sha_256(&sighash_signature.to_bytes().unwrap()).to_bytes();
+            let sig_bytes = sighash_signature
+                .to_bytes()
+                .map_err(|e| MinerError::Signing(e.to_string()))?;
+
+            let sig256 = Hash::sha_256(&sig_bytes).to_bytes();
+
+            stats.record(thread, &sig256, &target_ref);
 
             if sig256.starts_with(&target_ref) {
                 stop_signal.store(true, Ordering::Relaxed);
 
                 std::thread::sleep(Duration::from_millis(100));
 
-                println!("\r🪄 {GREEN}{}", hex::encode(sig256));
-                sender
-                    .send(MinerResult(sighash_signature, ephemeral_key))
-                    .unwrap();
+                tracing::debug!(thread, hash = %hex::encode(sig256), "solution found");
 
-                return;
-            } else if !stop_signal.load(Ordering::Relaxed) {
+                if print_progress {
+                    println!("\r🪄 {GREEN}{}", hex::encode(sig256));
+                }
+
+                return sender
+                    .send(MinerResult(sighash_signature, ephemeral_key))
+                    .map_err(|_| MinerError::Cancelled);
+            } else if print_progress && !stop_signal.load(Ordering::Relaxed) {
                 print!("\r{RED}{}", hex::encode(sig256));
             }
         }
     }
 
+    /// Resolves the configured thread count, 0 meaning all available cores
+    pub fn thread_count(threads: usize) -> Res<usize> {
+        match threads {
+            0 => Ok(std::thread::available_parallelism()?.get()),
+            n => Ok(n),
+        }
+    }
+
     /// This is where we set up our multithreading
-    pub fn mine_target(sig_hash_preimage: &[u8], target: &[u8]) -> Res<MinerResult> {
-        let available_threads = std::thread::available_parallelism()?.get();
-        println!("{CYAN}[{} threads]{RESET_COLOR}", available_threads);
-        println!();
+    pub fn mine_target(
+        sig_hash_preimage: &[u8],
+        target: &[u8],
+        stats: Arc<MiningStats>,
+    ) -> Res<MinerResult> {
+        let available_threads = stats.threads();
+
+        metrics().job_started(Arc::clone(&stats));
+
+        tracing::info!(threads = available_threads, target = %hex::encode(target), "mining started");
+
+        output::emit(Event::Threads {
+            threads: available_threads,
+        });
 
         let (sender, receiver) = mpsc::channel::<MinerResult>();
+        let (failure_sender, failures) = mpsc::channel::<MinerError>();
 
         let stop_signal = Arc::new(AtomicBool::new(false));
 
@@ -140,81 +372,426 @@ impl MagicMiner {
         let preimage_arc = Arc::new(sig_hash_preimage.to_vec());
         let target_arc = Arc::new(target.to_vec());
 
-        for _ in 0..available_threads {
+        for thread in 0..available_threads {
             let sender_clone = sender.clone();
             let stop_signal_clone = Arc::clone(&stop_signal);
+            let stats_clone = Arc::clone(&stats);
             let preimage_clone = Arc::clone(&preimage_arc);
             let target_clone = Arc::clone(&target_arc);
+            let failure_clone = failure_sender.clone();
 
             let handle = std::thread::spawn(move || {
-                MagicMiner::sign(
-                    sender_clone,
-                    &stop_signal_clone,
-                    preimage_clone,
-                    target_clone,
-                );
+                let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                    MagicMiner::sign(
+                        sender_clone,
+                        &stop_signal_clone,
+                        &stats_clone,
+                        thread,
+                        preimage_clone,
+                        target_clone,
+                    )
+                }));
+
+                let cause = match outcome {
+                    Ok(Ok(())) | Ok(Err(MinerError::Cancelled)) => return,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(payload) => panic_message(payload),
+                };
+
+                tracing::error!(thread, %cause, "worker failed");
+
+                // a failed worker takes the whole job down with it
+                stop_signal_clone.store(true, Ordering::Relaxed);
+                let _ = failure_clone.send(MinerError::Worker { thread, cause });
             });
 
             handles.push(handle);
         }
 
-        let result = match receiver.recv() {
-            Ok(v) => v,
-            Err(err) => return Err(anyhow::format_err!("recv error: {:?}", err)),
-        };
+        // only workers hold senders now, so a disconnect means they all exited
+        drop(sender);
+        drop(failure_sender);
 
-        stop_signal.store(true, Ordering::Relaxed);
+        let mut last_report = Instant::now();
 
-        for handle in handles {
-            if let Err(err) = handle.join() {
-                return Err(anyhow::format_err!("thread join error: {:?}", err));
+        let result = loop {
+            if let Ok(failure) = failures.try_recv() {
+                let _ = stop_workers(&stop_signal, handles);
+                metrics().job_finished(false);
+                return Err(failure);
             }
+
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(v) => break v,
+                Err(RecvTimeoutError::Timeout) => {
+                    if stats.is_cancelled() {
+                        tracing::warn!(attempts = stats.attempts(), "mining cancelled");
+
+                        let _ = stop_workers(&stop_signal, handles);
+                        metrics().job_finished(false);
+
+                        return Err(MinerError::Cancelled);
+                    }
+
+                    if last_report.elapsed() >= STATS_INTERVAL {
+                        last_report = Instant::now();
+
+                        tracing::debug!(
+                            attempts = stats.attempts(),
+                            hashrate = stats.hashrate(),
+                            best_prefix = stats.best_prefix(),
+                            "mining progress"
+                        );
+
+                        output::emit(Event::Stats {
+                            attempts: stats.attempts(),
+                            elapsed_secs: stats.elapsed().as_secs_f64(),
+                            hashrate: stats.hashrate(),
+                            best_prefix: stats.best_prefix(),
+                        });
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    let _ = stop_workers(&stop_signal, handles);
+                    metrics().job_finished(false);
+
+                    return Err(failures.try_recv().unwrap_or(MinerError::Worker {
+                        thread: 0,
+                        cause: String::from("all workers exited without a solution"),
+                    }));
+                }
+            }
+        };
+
+        let stopped = stop_workers(&stop_signal, handles);
+
+        metrics().job_finished(true);
+
+        tracing::info!(
+            attempts = stats.attempts(),
+            elapsed_secs = stats.elapsed().as_secs_f64(),
+            "mining finished"
+        );
+
+        // the solution is still valid if another worker died while stopping
+        if let Err(e) = stopped {
+            tracing::warn!(error = %e, "worker failed after the solution was found");
         }
 
         Ok(result)
     }
 
+    /// Emits the end-of-job summary, saves it with the job and folds it into the lifetime stats
+    pub fn report_job(
+        stats: &MiningStats,
+        solved: bool,
+        user: &str,
+        txid: &str,
+        vout: usize,
+        target: &[u8],
+    ) {
+        let summary = JobSummary::new(stats, solved);
+
+        if let Err(e) = summary.save(txid, vout, target) {
+            tracing::warn!(error = %e, "could not save job record");
+        }
+
+        let user = match user.is_empty() {
+            true => "default",
+            false => user,
+        };
+
+        let lifetime = match LifetimeStats::record(user, &summary) {
+            Ok(lifetime) => lifetime,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not update lifetime stats");
+                LifetimeStats::default()
+            }
+        };
+
+        tracing::info!(
+            attempts = summary.attempts,
+            elapsed_secs = summary.elapsed_secs,
+            luck = ?summary.luck,
+            "job summary"
+        );
+
+        output::emit(Event::Summary {
+            solved: summary.solved,
+            attempts: summary.attempts,
+            elapsed_secs: summary.elapsed_secs,
+            hashrate: summary.hashrate,
+            expected_attempts: summary.expected_attempts,
+            luck: summary.luck,
+            best_prefix: summary.best_prefix,
+            best_hash: summary.best_hash,
+            histogram: summary.histogram,
+            estimated_attempts: summary.estimated_attempts,
+            lifetime_attempts: lifetime.attempts,
+            lifetime_hashrate: lifetime.hashrate(),
+            lifetime_luck: lifetime.luck(),
+        });
+    }
+
+    #[tracing::instrument(
+        name = "job",
+        skip_all,
+        fields(txid = %from.get_id_hex().unwrap_or_default(), vout = output_index)
+    )]
     pub async fn solve_puzzle(
         from: Transaction,
         output_index: usize,
         target: &[u8],
-        pay_to_script: Script,
+        payouts: Vec<Payout>,
         miner_config: Config,
     ) -> Res<()> {
-        let mut tx = Transaction::new(1, 0);
-
         let target_output = match from.get_output(output_index) {
             Some(x) => x,
-            None => return Ok(()),
+            None => {
+                return Err(MinerError::ScriptMismatch(format!(
+                    "output {} does not exist",
+                    output_index
+                )))
+            }
         };
 
         let value = &target_output.get_satoshis();
 
-        let mut tx_in = TxIn::default();
+        output::emit(Event::PuzzleDetected {
+            txid: from.get_id_hex()?,
+            vout: output_index,
+            satoshis: *value,
+        });
 
         let locking_script = target_output.get_script_pub_key();
 
-        tx_in.set_satoshis(*value);
-        tx_in.set_locking_script(&locking_script);
+        if !MagicMiner::is_21e8_out(&locking_script)? {
+            return Err(MinerError::ScriptMismatch(format!(
+                "output {} is not a 21e8 script",
+                output_index
+            )));
+        }
+
+        // only spend from the funding wallet when the puzzle cannot pay for itself
+        let funding = match MagicMiner::build_tx(&from, output_index, &payouts, &miner_config, None)
+        {
+            Err(MinerError::InsufficientValue { .. }) if !miner_config.funding_key.is_empty() => {
+                Some(Funding::fetch(&miner_config.funding_key).await?)
+            }
+            Err(e) => return Err(e),
+            Ok(_) => None,
+        };
+
+        let (mut tx, fees) = MagicMiner::build_tx(
+            &from,
+            output_index,
+            &payouts,
+            &miner_config,
+            funding.as_ref(),
+        )?;
+
+        output::emit(Event::Fee {
+            size: fees.size,
+            fee_rate: fees.fee_rate,
+            fee: fees.fee,
+            payout: fees.payout,
+        });
+
+        let input = tx
+            .get_input(0)
+            .ok_or_else(|| MinerError::ScriptMismatch(String::from("missing input")))?;
+
+        let sats = *value;
+
+        let sig_hash_preimage =
+            tx.sighash_preimage(SigHash::InputsOutputs, 0, &locking_script, sats)?;
+
+        output::emit(Event::Target {
+            target: hex::encode(target),
+            difficulty: 256f64.powi(target.len() as i32),
+        });
+
+        let stats = Arc::new(MiningStats::new(
+            MagicMiner::thread_count(miner_config.threads)?,
+            target,
+        ));
+
+        let dashboard = match output::mode() {
+            OutputMode::Dashboard => Some(Dashboard::spawn(
+                DashboardJob {
+                    txid: from.get_id_hex()?,
+                    vout: output_index,
+                    target: hex::encode(target),
+                    satoshis: sats,
+                    payout: payouts
+                        .iter()
+                        .map(|p| p.address.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                },
+                Arc::clone(&stats),
+            )?),
+            _ => None,
+        };
+
+        let mined = MagicMiner::mine_target(&sig_hash_preimage, target, Arc::clone(&stats));
+
+        if output::is_text() {
+            print!("{RESET_COLOR}");
+        }
+
+        MagicMiner::report_job(
+            &stats,
+            mined.is_ok(),
+            &miner_config.pay_to,
+            &from.get_id_hex()?,
+            output_index,
+            target,
+        );
+
+        let MinerResult(sig, ephemeral_key) = mined?;
+
+        let public_key = &ephemeral_key.to_public_key()?;
+
+        let mut unlocking_script = Script::default();
+
+        unlocking_script.push(ScriptBit::Push(sig.to_bytes()?));
+        unlocking_script.push(ScriptBit::Push(public_key.to_bytes()?));
+
+        let prev_txid = &input.get_prev_tx_id(None);
+
+        let tx_in_final = TxIn::new(prev_txid, output_index as u32, &unlocking_script, None);
+
+        tx.set_input(0, &tx_in_final);
+
+        if let Some(funding) = &funding {
+            funding.sign(&mut tx, 1)?;
+        }
+
+        // derived addresses only count as used once a solution pays them
+        for path in payouts.iter().filter_map(|p| p.derivation_path.as_deref()) {
+            XpubWallet::new(&miner_config.xpub, &miner_config.derivation_path)?.commit(path)?;
+        }
+
+        let tx_hex = tx.to_hex()?;
+        let sig_bytes = sig.to_bytes()?;
+
+        output::emit(Event::Solution {
+            target: hex::encode(target),
+            hash: hex::encode(Hash::sha_256(&sig_bytes).to_bytes()),
+            signature: hex::encode(&sig_bytes),
+            ephemeral_pubkey: public_key.to_hex()?,
+            raw_tx: tx_hex.clone(),
+        });
+
+        let broadcast_result = match miner_config.autopublish {
+            true => Some(MagicMiner::broadcast_tx(&tx_hex).await),
+            false => None,
+        };
+
+        // only a broadcast solution pays out
+        if let Some(Ok(_)) = &broadcast_result {
+            metrics().earned(fees.payout);
+        }
+
+        if let Some(result) = &broadcast_result {
+            output::emit(Event::Broadcast {
+                success: result.is_ok(),
+                response: match result {
+                    Ok(response) => response.trim().to_string(),
+                    Err(e) => e.to_string(),
+                },
+                path: None,
+            });
+        }
+
+        if miner_config.autosave {
+            let path = utils::write_to_file(&from.get_id_hex()?, &tx_hex)?;
+
+            let paid: Vec<Value> = payouts
+                .iter()
+                .enumerate()
+                .map(|(i, payout)| {
+                    json!({
+                        "address": payout.address,
+                        "vout": i,
+                        "satoshis": tx.get_output(i).map_or(0, |o| o.get_satoshis()),
+                        "derivation_path": payout.derivation_path,
+                    })
+                })
+                .collect();
+
+            let metadata = json!({
+                "txid": tx.get_id_hex()?,
+                "puzzle_txid": from.get_id_hex()?,
+                "vout": output_index,
+                "target": hex::encode(target),
+                "fee": fees.fee,
+                "payouts": paid,
+            });
+
+            utils::write_metadata(&path, &metadata)?;
+
+            tracing::info!(%path, "solution saved");
+            output::emit(Event::Saved { path });
+        }
+
+        if let Some(dashboard) = dashboard {
+            dashboard.finish();
+        }
+
+        if let Some(Err(e)) = broadcast_result {
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Builds the unsigned spend of a puzzle with its payout and Miner ID outputs, paying the configured fee rate.
+    /// With `funding` the fee comes from the funding input instead and the payouts get the whole puzzle value.
+    pub fn build_tx(
+        from: &Transaction,
+        output_index: usize,
+        payouts: &[Payout],
+        miner_config: &Config,
+        funding: Option<&Funding>,
+    ) -> Res<(Transaction, FeeBreakdown)> {
+        let target_output = from.get_output(output_index).ok_or_else(|| {
+            MinerError::ScriptMismatch(format!("output {} does not exist", output_index))
+        })?;
+
+        let value = target_output.get_satoshis();
+
+        let mut tx = Transaction::new(1, 0);
+
+        let mut tx_in = TxIn::default();
+
+        tx_in.set_satoshis(value);
+        tx_in.set_locking_script(&target_output.get_script_pub_key());
         tx_in.set_prev_tx_id(&from.get_id_bytes()?);
         tx_in.set_vout(output_index as u32);
 
         tx.add_input(&tx_in);
 
-        let minerid_fee = match miner_config.miner_id.enabled {
-            true => 300u64,
-            false => 218u64,
-        };
+        if let Some(funding) = funding {
+            tx.add_input(&funding.tx_in());
+        }
 
-        let p2pkh = TxOut::new(value - minerid_fee, &pay_to_script);
+        // payouts are filled in once the size, and so the fee, is known
+        for payout in payouts {
+            tx.add_output(&TxOut::new(0u64, &payout.script));
+        }
 
-        tx.add_output(&p2pkh);
+        if let Some(funding) = funding {
+            tx.add_output(&TxOut::new(0u64, &funding.script));
+        }
 
         if miner_config.miner_id.enabled {
             let miner_priv = PrivateKey::from_wif(&miner_config.miner_id.priv_key)?;
             let miner_pub = miner_priv.to_public_key()?;
 
-            let sig = ECDSA::sign_digest_with_deterministic_k(&miner_priv, &from.get_id_bytes()?)?;
+            let sig = ECDSA::sign_digest_with_deterministic_k(&miner_priv, &from.get_id_bytes()?)
+                .map_err(|e| MinerError::Signing(e.to_string()))?;
 
             let schema = json!({
                 "id": miner_pub.to_hex()?,
@@ -239,139 +816,689 @@ impl MagicMiner {
             tx.add_output(&TxOut::new(0u64, &op_return_script));
         }
 
-        let input = tx.get_input(0).unwrap();
-
-        let locking_script = input.get_locking_script().unwrap();
+        let mut fees = match funding {
+            Some(funding) => {
+                FeeBreakdown::estimate(&tx, value + funding.satoshis, miner_config.fee_rate)?
+            }
+            None => FeeBreakdown::estimate(&tx, value, miner_config.fee_rate)?,
+        };
 
-        let sats = input.get_satoshis().unwrap();
+        if let Some(funding) = funding {
+            let required = fees.fee + DUST_LIMIT;
 
-        if !MagicMiner::is_21e8_out(&locking_script)? {
-            return Ok(());
-        }
+            if funding.satoshis < required {
+                return Err(MinerError::InsufficientValue {
+                    value: funding.satoshis,
+                    required,
+                });
+            }
 
-        let sig_hash_preimage =
-            tx.sighash_preimage(SigHash::InputsOutputs, 0, &locking_script, sats)?;
+            let change = funding.satoshis - fees.fee;
+            tx.set_output(payouts.len(), &TxOut::new(change, &funding.script));
 
-        let MinerResult(sig, ephemeral_key) = MagicMiner::mine_target(&sig_hash_preimage, target)?;
+            fees.value = value;
+            fees.payout = value;
+        }
 
-        print!("{RESET_COLOR}");
+        let amounts = Payout::split(payouts, fees.payout)?;
 
-        let public_key = &ephemeral_key.to_public_key()?;
+        for (i, (payout, amount)) in payouts.iter().zip(amounts).enumerate() {
+            tx.set_output(i, &TxOut::new(amount, &payout.script));
+        }
 
-        let mut unlocking_script = Script::default();
+        Ok((tx, fees))
+    }
 
-        unlocking_script.push(ScriptBit::Push(sig.to_bytes()?));
-        unlocking_script.push(ScriptBit::Push(public_key.to_bytes()?));
+    /// Checks a puzzle can pay for itself before any payout is resolved
+    pub fn check_puzzle(
+        from: &Transaction,
+        output_index: usize,
+        miner_config: &Config,
+    ) -> Res<FeeBreakdown> {
+        let shares = match miner_config.payouts.is_empty() {
+            true => vec![Share::Remainder],
+            false => miner_config
+                .payouts
+                .iter()
+                .map(PayoutRule::share)
+                .collect::<Res<Vec<Share>>>()?,
+        };
 
-        let prev_txid = &input.get_prev_tx_id(None);
+        let mut placeholders = Vec::with_capacity(shares.len());
 
-        let tx_in_final = TxIn::new(prev_txid, output_index as u32, &unlocking_script, None);
+        for share in shares {
+            placeholders.push(Payout {
+                address: String::new(),
+                script: fee::placeholder_p2pkh()?,
+                share,
+                derivation_path: None,
+            });
+        }
 
-        tx.set_input(0, &tx_in_final);
+        match MagicMiner::build_tx(from, output_index, &placeholders, miner_config, None) {
+            Err(MinerError::InsufficientValue { value, required })
+                if !miner_config.funding_key.is_empty() =>
+            {
+                tracing::info!(
+                    value,
+                    required,
+                    "puzzle needs a funding input to pay the fee"
+                );
 
-        println!(
-            "\nSigned {GREEN}{}{RESET_COLOR} with {}\n",
-            hex::encode(target),
-            ephemeral_key.to_wif()?
-        );
+                let funding = Funding::placeholder()?;
+                let (_, fees) = MagicMiner::build_tx(
+                    from,
+                    output_index,
+                    &placeholders,
+                    miner_config,
+                    Some(&funding),
+                )?;
 
-        let tx_hex = tx.to_hex()?;
+                Ok(fees)
+            }
+            result => result.map(|(_, fees)| fees),
+        }
+    }
 
-        println!("{}{}{}\n", YELLOW, &tx_hex, RESET_COLOR);
+    /// Returns the payout left after the fee, refusing puzzles that cannot cover it
+    pub fn check_value(value: u64, fee: u64) -> Res<u64> {
+        let required = fee.saturating_add(DUST_LIMIT);
 
-        if miner_config.autopublish {
-            let response = MagicMiner::broadcast_tx(&tx_hex).await?;
-            println!("Success! {response}");
+        if value < required {
+            return Err(MinerError::InsufficientValue { value, required });
         }
 
-        if miner_config.autosave {
-            utils::write_to_file(&from.get_id_hex()?, &tx_hex)?;
+        if fee > value / 2 {
+            tracing::warn!(value, fee, "fee takes more than half of the puzzle value");
         }
 
-        Ok(())
+        Ok(value - fee)
     }
 
-    pub async fn start() -> Res<()> {
-        let txid = Text::new("Target TXID").prompt()?;
-
-        if txid.is_empty() || !utils::is_valid_txid(&txid) {
-            println!("Invalid txid");
-            return Ok(());
-        }
-
-        let tx = MagicMiner::get_tx(&txid).await?;
+    pub fn puzzle_target(script: &Script) -> Option<Vec<u8>> {
+        script.get_script_bit(1)?.to_vec()
+    }
 
-        let mut index = None;
-        let outputs = tx.get_noutputs();
-        let mut target_script: Script = Script::default();
+    pub fn find_21e8_outputs(tx: &Transaction) -> Res<Vec<(usize, Vec<u8>)>> {
+        let mut found = Vec::new();
 
-        for i in 0..outputs {
-            target_script = match tx.get_output(i) {
+        for i in 0..tx.get_noutputs() {
+            let target_script = match tx.get_output(i) {
                 Some(output) => output.get_script_pub_key(),
                 None => continue,
             };
 
             if MagicMiner::is_21e8_out(&target_script)? {
-                index = Some(i);
-                break;
+                if let Some(target) = MagicMiner::puzzle_target(&target_script) {
+                    found.push((i, target));
+                }
             }
         }
 
-        let target = match index.is_some() {
-            true => target_script.get_script_bit(1).unwrap().to_vec().unwrap(),
-            false => {
-                println!("No 21e8 scripts found.");
-                return Ok(());
+        Ok(found)
+    }
+
+    pub fn find_21e8_output(tx: &Transaction) -> Res<Option<(usize, Vec<u8>)>> {
+        Ok(MagicMiner::find_21e8_outputs(tx)?.into_iter().next())
+    }
+
+    /// Validates an explicitly requested output index
+    pub fn select_output(tx: &Transaction, vout: usize) -> Res<(usize, Vec<u8>)> {
+        let script = match tx.get_output(vout) {
+            Some(output) => output.get_script_pub_key(),
+            None => {
+                return Err(MinerError::ScriptMismatch(format!(
+                    "output {} does not exist",
+                    vout
+                )))
             }
         };
 
-        let miner_config = match Config::read_from_toml() {
-            Ok(config) => config,
+        match MagicMiner::is_21e8_out(&script)? {
+            true => match MagicMiner::puzzle_target(&script) {
+                Some(target) => Ok((vout, target)),
+                None => Err(MinerError::ScriptMismatch(format!(
+                    "output {} has no target",
+                    vout
+                ))),
+            },
+            false => Err(MinerError::ScriptMismatch(format!(
+                "output {} is not a 21e8 script",
+                vout
+            ))),
+        }
+    }
+
+    pub fn load_config() -> Res<Config> {
+        match Config::read_from_toml() {
+            Ok(config) => Ok(config),
             Err(e) => {
-                eprintln!("\nInvalid miner config.\n{}", e);
-                Prompt::run_setup()?
+                tracing::warn!(error = %e, "invalid miner config, running setup");
+                Prompt::run_setup()
             }
-        };
+        }
+    }
 
+    /// `satoshis` is a hint for Paymail hosts that size their payment destinations
+    pub async fn resolve_pay_to(
+        miner_config: &Config,
+        offline: bool,
+        satoshis: u64,
+    ) -> Res<(String, Script)> {
         let mut to_address: String = miner_config.pay_to.clone();
-        let p2pkh_script: Script;
 
         while to_address.is_empty() {
-            to_address =
-                Text::new("Pay solved puzzle out to (1handle, $handle, PayMail or p2pkh address)")
-                    .prompt()?;
+            to_address = Text::new(
+                "Pay solved puzzle out to (1handle, $handle, PayMail, p2pkh address or script)",
+            )
+            .prompt()?;
         }
 
         loop {
-            match P2PKHAddress::from_string(&to_address) {
-                Ok(address) => {
-                    p2pkh_script = address.get_locking_script()?;
-                    break;
+            match Destination::parse(&to_address).map(|d| d.script()) {
+                Ok(Some(Ok(script))) => return Ok((to_address, script)),
+                Ok(Some(Err(e))) | Err(e) => {
+                    tracing::warn!(address = %to_address, error = %e, "invalid payout destination");
+                    to_address =
+                        Text::new("Pay solved puzzle out to (P2PKH address or script)").prompt()?;
                 }
-                Err(e) => {
-                    println!("{}\n", e);
-
-                    // try polynym
-                    to_address = match Config::fetch_polynym_address(&to_address).await {
-                        Ok(v) => {
-                            println!("Polynym address found: {}", v);
-                            v
-                        }
+                Ok(None) => {
+                    tracing::debug!(address = %to_address, "not a P2PKH address");
+
+                    if offline {
+                        to_address =
+                            Text::new("Pay solved puzzle out to (P2PKH address or script)")
+                                .prompt()?;
+                        continue;
+                    }
+
+                    match PaymailClient::default()
+                        .require_signature(!miner_config.allow_unsigned_paymail)
+                        .resolve(&to_address, satoshis)
+                        .await
+                    {
+                        Ok(destination) => return Ok((to_address, destination.script)),
                         Err(e) => {
-                            println!("Could not fetch address from Polynym: {:?}", e);
-                            Text::new("Pay solved puzzle out to (P2PKH address)").prompt()?
+                            tracing::warn!(error = %e, "could not resolve Paymail");
+                            to_address =
+                                Text::new("Pay solved puzzle out to (P2PKH address or script)")
+                                    .prompt()?;
                         }
-                    };
+                    }
                 }
             };
         }
+    }
+
+    /// Resolves the configured split payouts, else a fresh address from the xpub,
+    /// else the single `pay_to` address
+    pub async fn resolve_payouts(
+        miner_config: &Config,
+        offline: bool,
+        satoshis: u64,
+    ) -> Res<Vec<Payout>> {
+        if miner_config.payouts.is_empty() && !miner_config.xpub.is_empty() {
+            let derived = XpubWallet::new(&miner_config.xpub, &miner_config.derivation_path)?
+                .next_address()?;
+
+            return Ok(vec![Payout {
+                address: derived.address,
+                script: derived.script,
+                share: Share::Remainder,
+                derivation_path: Some(derived.path),
+            }]);
+        }
+
+        if miner_config.payouts.is_empty() {
+            let (address, script) =
+                MagicMiner::resolve_pay_to(miner_config, offline, satoshis).await?;
+            return Ok(Payout::single(address, script));
+        }
+
+        let mut payouts = Vec::with_capacity(miner_config.payouts.len());
+
+        for rule in &miner_config.payouts {
+            let share = rule.share()?;
+            let (address, script) =
+                MagicMiner::resolve_address(miner_config, &rule.address, offline, satoshis).await?;
+
+            payouts.push(Payout {
+                address,
+                script,
+                share,
+                derivation_path: None,
+            });
+        }
+
+        Ok(payouts)
+    }
+
+    /// Non-interactive counterpart of `resolve_pay_to`
+    pub async fn resolve_address(
+        miner_config: &Config,
+        to_address: &str,
+        offline: bool,
+        satoshis: u64,
+    ) -> Res<(String, Script)> {
+        if let Some(script) = Destination::parse(to_address)?.script() {
+            return Ok((to_address.to_string(), script?));
+        }
+
+        if offline {
+            return Err(MinerError::Config(format!(
+                "{} is not a P2PKH address or script and cannot be resolved offline",
+                to_address
+            )));
+        }
+
+        let destination = PaymailClient::default()
+            .require_signature(!miner_config.allow_unsigned_paymail)
+            .resolve(to_address, satoshis)
+            .await?;
+
+        Ok((to_address.to_string(), destination.script))
+    }
+
+    pub async fn mine_tx(
+        tx: Transaction,
+        vout: Option<usize>,
+        miner_config: Config,
+        offline: bool,
+    ) -> Res<()> {
+        let (index, target) = match vout {
+            Some(vout) => MagicMiner::select_output(&tx, vout)?,
+            None => {
+                let mut puzzles = MagicMiner::find_21e8_outputs(&tx)?;
+
+                match puzzles.len() {
+                    0 => {
+                        output::emit(Event::NoPuzzle {
+                            txid: tx.get_id_hex()?,
+                        });
+                        return Ok(());
+                    }
+                    1 => puzzles.remove(0),
+                    _ => {
+                        let options: Vec<String> = puzzles
+                            .iter()
+                            .map(|(i, target)| {
+                                let value = tx.get_output(*i).map_or(0, |o| o.get_satoshis());
+
+                                match MagicMiner::check_puzzle(&tx, *i, &miner_config) {
+                                    Ok(_) => {
+                                        format!("{}: {} ({} sats)", i, hex::encode(target), value)
+                                    }
+                                    Err(_) => format!(
+                                        "{}: {} ({} sats, too small to pay the fee)",
+                                        i,
+                                        hex::encode(target),
+                                        value
+                                    ),
+                                }
+                            })
+                            .collect();
+
+                        let choice = Select::new(
+                            "Multiple 21e8 outputs found",
+                            options.iter().map(String::as_str).collect(),
+                        )
+                        .prompt()?;
+
+                        let position = options.iter().position(|o| o == choice).unwrap_or(0);
+                        puzzles.remove(position)
+                    }
+                }
+            }
+        };
+
+        // reject dust puzzles before asking for a payout or spending hours mining
+        let fees = MagicMiner::check_puzzle(&tx, index, &miner_config)?;
+
+        let payouts = MagicMiner::resolve_payouts(&miner_config, offline, fees.payout).await?;
+
+        for payout in &payouts {
+            output::emit(Event::Payout {
+                address: payout.address.clone(),
+            });
+        }
+
+        MagicMiner::solve_puzzle(tx, index, &target, payouts, miner_config).await
+    }
+
+    /// Resolves a txid, falling back to the reversed byte order if nothing is found.
+    /// Network failures are returned as they are, retrying would only double the requests.
+    pub async fn get_tx_any_order(txid: &str) -> Res<Transaction> {
+        let err = match MagicMiner::get_tx(txid).await {
+            Ok(tx) => return Ok(tx),
+            Err(e @ MinerError::Parse(_)) => e,
+            Err(e) => return Err(e),
+        };
+
+        let reversed = utils::reverse_txid(txid)?;
+
+        match MagicMiner::get_tx(&reversed).await {
+            Ok(tx) => {
+                tracing::warn!(txid, %reversed, "txid is in reversed byte order");
+                Ok(tx)
+            }
+            Err(_) => Err(err),
+        }
+    }
+
+    pub async fn start() -> Res<()> {
+        let input = Text::new("Target TXID, outpoint (txid:vout) or explorer URL").prompt()?;
+
+        let outpoint = match utils::parse_outpoint(&input) {
+            Some(outpoint) => outpoint,
+            None => {
+                return Err(MinerError::Parse(format!(
+                    "{} is not a txid, outpoint or explorer URL",
+                    input.trim()
+                )))
+            }
+        };
+
+        let miner_config = MagicMiner::load_config()?;
+
+        let tx = MagicMiner::get_tx_any_order(&outpoint.txid).await?;
+        let txid = tx.get_id_hex()?;
+
+        if !miner_config.headers.is_empty() {
+            MagicMiner::verify_inclusion(&txid, &miner_config.headers).await?;
+            output::emit(Event::ProofVerified { txid: txid.clone() });
+        }
+
+        MagicMiner::mine_tx(tx, outpoint.vout, miner_config, false).await
+    }
+
+    /// Mines a raw transaction without touching the network and saves the result for later broadcasting
+    pub async fn start_offline() -> Res<()> {
+        let input = Text::new("Raw transaction hex, file path or - for stdin").prompt()?;
+
+        let tx = utils::read_tx(input.trim())?;
+
+        TxCache::default().put(&tx)?;
+
+        let mut miner_config = MagicMiner::load_config()?;
+        miner_config.autopublish = false;
+        miner_config.autosave = true;
+
+        output::emit(Event::Loaded {
+            txid: tx.get_id_hex()?,
+        });
+
+        let vout = Text::new("Output index (or press Enter to detect)").prompt()?;
+
+        let vout = match vout.trim() {
+            "" => None,
+            v => Some(v.parse::<usize>()?),
+        };
+
+        MagicMiner::mine_tx(tx, vout, miner_config, true).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64*, seeded so failures reproduce
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+
+        fn target(&mut self) -> Vec<u8> {
+            let len = self.below(MAX_TARGET_LEN as u64 - 1) as usize;
+            [vec![0x21, 0xe8], self.bytes(len)].concat()
+        }
+    }
+
+    fn random_puzzle(rng: &mut Rng) -> (Vec<u8>, Vec<u8>, Script) {
+        let hash = rng.bytes(32);
+        let target = rng.target();
+        let script = MagicMiner::puzzle_script(&hash, &target).unwrap();
+        (hash, target, script)
+    }
+
+    fn random_script(rng: &mut Rng) -> Vec<u8> {
+        match rng.below(3) {
+            0 => {
+                let len = rng.below(120) as usize;
+                rng.bytes(len)
+            }
+            // near misses: valid puzzles with one byte changed or cut short
+            1 => {
+                let mut bytes = random_puzzle(rng).2.to_bytes();
+                let i = rng.below(bytes.len() as u64) as usize;
+                bytes[i] = rng.next() as u8;
+                bytes
+            }
+            _ => {
+                let mut bytes = random_puzzle(rng).2.to_bytes();
+                let len = rng.below(bytes.len() as u64) as usize;
+                bytes.truncate(len);
+                bytes
+            }
+        }
+    }
+
+    fn random_tx(rng: &mut Rng) -> Transaction {
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&TxIn::new(&rng.bytes(32), 0, &Script::default(), None));
+
+        for _ in 0..1 + rng.below(4) {
+            let script = match rng.below(2) {
+                0 => random_puzzle(rng).2,
+                _ => Script::from_bytes(&random_script(rng))
+                    .unwrap_or_else(|_| Script::from_asm_string("OP_FALSE OP_RETURN").unwrap()),
+            };
+
+            tx.add_output(&TxOut::new(rng.below(200), &script));
+        }
 
-        println!(
-            "{GREEN}■{RESET_COLOR} Paying to: {PURPLE}{}{RESET_COLOR}",
-            &to_address
+        tx
+    }
+
+    #[test]
+    fn panic_messages_are_extracted() {
+        assert_eq!(panic_message(Box::new("static")), "static");
+        assert_eq!(panic_message(Box::new(String::from("owned"))), "owned");
+        assert_eq!(panic_message(Box::new(42)), "unknown panic");
+    }
+
+    #[test]
+    fn stop_workers_reports_the_first_failed_thread() {
+        let stop_signal = Arc::new(AtomicBool::new(false));
+
+        let handles: Vec<JoinHandle<()>> = (0..4)
+            .map(|thread| {
+                let stop_signal = stop_signal.clone();
+
+                std::thread::spawn(move || {
+                    if thread >= 2 {
+                        panic!("worker {} died", thread);
+                    }
+
+                    while !stop_signal.load(Ordering::Relaxed) {
+                        std::thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+
+        match stop_workers(&stop_signal, handles) {
+            Err(MinerError::Worker { thread, cause }) => {
+                assert_eq!(thread, 2);
+                assert_eq!(cause, "worker 2 died");
+            }
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        assert!(stop_signal.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn stop_workers_succeeds_when_every_thread_exits() {
+        let stop_signal = AtomicBool::new(false);
+        let handles = (0..3).map(|_| std::thread::spawn(|| ())).collect();
+
+        assert!(stop_workers(&stop_signal, handles).is_ok());
+    }
+
+    #[test]
+    fn detection_never_panics_on_random_scripts() {
+        let mut rng = Rng(0x21e8);
+
+        for _ in 0..5000 {
+            let script = match Script::from_bytes(&random_script(&mut rng)) {
+                Ok(script) => script,
+                Err(_) => continue,
+            };
+
+            if let Ok(true) = MagicMiner::is_21e8_out(&script) {
+                let target = MagicMiner::puzzle_target(&script).unwrap();
+                assert!(target.starts_with(&[0x21, 0xe8]));
+                assert!(target.len() <= MAX_TARGET_LEN);
+            }
+        }
+    }
+
+    #[test]
+    fn puzzle_scripts_round_trip() {
+        let mut rng = Rng(0x5eed);
+
+        for _ in 0..500 {
+            let (hash, target, script) = random_puzzle(&mut rng);
+
+            let parsed = Script::from_bytes(&script.to_bytes()).unwrap();
+
+            assert!(MagicMiner::is_21e8_out(&parsed).unwrap());
+            assert_eq!(MagicMiner::puzzle_target(&parsed), Some(target));
+            assert_eq!(
+                parsed.get_script_bit(0).and_then(|bit| bit.to_vec()),
+                Some(hash)
+            );
+        }
+    }
+
+    #[test]
+    fn puzzle_script_rejects_bad_hashes_and_targets() {
+        let mut rng = Rng(0xbad);
+
+        for _ in 0..200 {
+            let len = rng.below(64) as usize;
+            let target = rng.target();
+
+            if len != 32 {
+                assert!(MagicMiner::puzzle_script(&rng.bytes(len), &target).is_err());
+            }
+        }
+
+        let hash = rng.bytes(32);
+        assert!(MagicMiner::puzzle_script(&hash, &[0x21, 0xe9]).is_err());
+        assert!(MagicMiner::puzzle_script(&hash, &[0x21]).is_err());
+        assert!(
+            MagicMiner::puzzle_script(&hash, &[vec![0x21, 0xe8], vec![0; 31]].concat()).is_err()
+        );
+        assert!(
+            MagicMiner::puzzle_script(&hash, &[vec![0x21, 0xe8], vec![0; 30]].concat()).is_ok()
         );
-        print!("{GREEN}■{RESET_COLOR} Mining output {} ", &index.unwrap());
+    }
+
+    #[test]
+    fn check_value_refuses_puzzles_below_fee_and_dust() {
+        assert_eq!(MagicMiner::check_value(100, 10).unwrap(), 90);
+        assert_eq!(MagicMiner::check_value(11, 10).unwrap(), 1);
+        assert_eq!(MagicMiner::check_value(1, 0).unwrap(), 1);
+
+        for (value, fee, expected) in [(10, 10, 11), (0, 0, 1), (5, 10, 11)] {
+            match MagicMiner::check_value(value, fee) {
+                Err(MinerError::InsufficientValue { value: v, required }) => {
+                    assert_eq!((v, required), (value, expected));
+                }
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn check_value_leaves_at_least_dust() {
+        let mut rng = Rng(0xfee);
+        let edges = [0, 1, 2, u64::MAX - 1, u64::MAX];
+
+        for i in 0..5000 {
+            let (value, fee) = match i % 3 {
+                0 => (rng.below(1000), rng.below(1000)),
+                1 => (rng.next(), rng.next()),
+                _ => (edges[rng.below(5) as usize], edges[rng.below(5) as usize]),
+            };
 
-        MagicMiner::solve_puzzle(tx, index.unwrap(), &target, p2pkh_script, miner_config).await
+            match MagicMiner::check_value(value, fee) {
+                Ok(payout) => {
+                    assert_eq!(payout + fee, value);
+                    assert!(payout >= DUST_LIMIT);
+                }
+                Err(MinerError::InsufficientValue { value: v, required }) => {
+                    assert_eq!(v, value);
+                    assert_eq!(required, fee.saturating_add(DUST_LIMIT));
+                    assert!(value < required);
+                }
+                Err(e) => panic!("unexpected error {}", e),
+            }
+        }
+    }
+
+    #[test]
+    fn check_puzzle_on_random_transactions() {
+        let mut rng = Rng(0xc0ffee);
+        let config = Config::default();
+
+        for _ in 0..300 {
+            let tx = random_tx(&mut rng);
+            let puzzles = MagicMiner::find_21e8_outputs(&tx).unwrap();
+
+            for vout in 0..tx.get_noutputs() + 1 {
+                let result = MagicMiner::check_puzzle(&tx, vout, &config);
+
+                let value = match tx.get_output(vout) {
+                    Some(output) => output.get_satoshis(),
+                    None => {
+                        assert!(matches!(result, Err(MinerError::ScriptMismatch(_))));
+                        continue;
+                    }
+                };
+
+                match result {
+                    Ok(fees) => {
+                        assert_eq!(fees.value, value);
+                        assert_eq!(fees.payout + fees.fee, value);
+                        assert!(fees.payout >= DUST_LIMIT);
+                        assert!(fees.fee > 0);
+                    }
+                    Err(MinerError::InsufficientValue { value: v, .. }) => assert_eq!(v, value),
+                    Err(e) => panic!("unexpected error {}", e),
+                }
+
+                if puzzles.iter().any(|(i, _)| *i == vout) {
+                    assert!(MagicMiner::select_output(&tx, vout).is_ok());
+                } else {
+                    assert!(MagicMiner::select_output(&tx, vout).is_err());
+                }
+            }
+        }
     }
 }
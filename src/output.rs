@@ -0,0 +1,446 @@
+use serde::Serialize;
+use std::io::Write;
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Bumped whenever an event or field is renamed or removed
+pub const SCHEMA_VERSION: u32 = 1;
+
+// ANSI escape
+pub(crate) const RED: &str = "\x1b[31m";
+pub(crate) const GREEN: &str = "\x1b[32m";
+pub(crate) const YELLOW: &str = "\x1b[33m";
+pub(crate) const PURPLE: &str = "\x1b[35m";
+pub(crate) const CYAN: &str = "\x1b[36m";
+pub(crate) const RESET_COLOR: &str = "\x1B[0m";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    #[default]
+    Text,
+    Json,
+    Dashboard,
+}
+
+static OUTPUT_MODE: OnceLock<OutputMode> = OnceLock::new();
+static SINK: Mutex<Option<Sender<Event>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    PuzzleDetected {
+        txid: String,
+        vout: usize,
+        satoshis: u64,
+    },
+    Payout {
+        address: String,
+    },
+    /// A 21e8 output listed by `inspect`
+    Output {
+        vout: usize,
+        target: String,
+        satoshis: u64,
+    },
+    NoPuzzle {
+        txid: String,
+    },
+    Loaded {
+        txid: String,
+    },
+    ProofVerified {
+        txid: String,
+    },
+    Verified {
+        txid: String,
+        valid: bool,
+    },
+    Target {
+        target: String,
+        /// Expected number of attempts to find a solution
+        difficulty: f64,
+    },
+    Threads {
+        threads: usize,
+    },
+    Fee {
+        size: usize,
+        /// Satoshis per 1000 bytes
+        fee_rate: u64,
+        fee: u64,
+        payout: u64,
+    },
+    Stats {
+        attempts: u64,
+        elapsed_secs: f64,
+        hashrate: f64,
+        best_prefix: usize,
+    },
+    Summary {
+        solved: bool,
+        attempts: u64,
+        elapsed_secs: f64,
+        hashrate: f64,
+        expected_attempts: f64,
+        luck: Option<f64>,
+        best_prefix: usize,
+        best_hash: String,
+        histogram: Vec<u64>,
+        estimated_attempts: f64,
+        lifetime_attempts: u64,
+        lifetime_hashrate: f64,
+        lifetime_luck: Option<f64>,
+    },
+    Solution {
+        target: String,
+        hash: String,
+        signature: String,
+        ephemeral_pubkey: String,
+        raw_tx: String,
+    },
+    Broadcast {
+        success: bool,
+        response: String,
+        /// Saved solution the transaction was read from
+        #[serde(skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    Saved {
+        path: String,
+    },
+    /// Unspent payouts of one address, listed by `wallet balance`
+    Balance {
+        address: String,
+        satoshis: u64,
+    },
+    /// A payout listed by `wallet history`
+    WalletUtxo {
+        txid: String,
+        vout: u32,
+        address: String,
+        satoshis: u64,
+        spent: bool,
+    },
+    /// SHA-256 a new job asks proof of work for
+    ContentHash {
+        sha256: String,
+    },
+    /// A funded transaction, before it is saved or broadcast
+    Built {
+        txid: String,
+        size: usize,
+        fee: u64,
+    },
+    /// A signed transaction printed instead of broadcast
+    RawTx {
+        txid: String,
+        raw_tx: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    schema: u32,
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Only the first call has any effect
+pub fn set_mode(mode: OutputMode) {
+    let _ = OUTPUT_MODE.set(mode);
+}
+
+pub fn mode() -> OutputMode {
+    OUTPUT_MODE.get().copied().unwrap_or_default()
+}
+
+pub fn is_json() -> bool {
+    mode() == OutputMode::Json
+}
+
+pub fn is_text() -> bool {
+    mode() == OutputMode::Text
+}
+
+/// Routes events to a dashboard instead of stdout
+pub fn attach(sender: Sender<Event>) {
+    if let Ok(mut sink) = SINK.lock() {
+        *sink = Some(sender);
+    }
+}
+
+pub fn detach() {
+    if let Ok(mut sink) = SINK.lock() {
+        *sink = None;
+    }
+}
+
+pub fn emit(event: Event) {
+    tracing::debug!(?event, "event");
+
+    match mode() {
+        OutputMode::Dashboard => {
+            let sink = SINK.lock().ok().and_then(|sink| sink.clone());
+
+            match sink {
+                Some(sender) => {
+                    let _ = sender.send(event);
+                }
+                None => print_text(&event),
+            }
+        }
+        OutputMode::Json => {
+            let envelope = Envelope {
+                schema: SCHEMA_VERSION,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                event: &event,
+            };
+
+            if let Ok(line) = serde_json::to_string(&envelope) {
+                let mut stdout = std::io::stdout().lock();
+                let _ = writeln!(stdout, "{}", line);
+                let _ = stdout.flush();
+            }
+        }
+        OutputMode::Text => print_text(&event),
+    }
+}
+
+fn print_text(event: &Event) {
+    match event {
+        Event::PuzzleDetected { vout, .. } => {
+            print!("{GREEN}■{RESET_COLOR} Mining output {} ", vout)
+        }
+        Event::Payout { address } => {
+            println!(
+                "{GREEN}■{RESET_COLOR} Paying to: {PURPLE}{}{RESET_COLOR}",
+                address
+            )
+        }
+        Event::Output {
+            vout,
+            target,
+            satoshis,
+        } => println!("{}\t{}\t{}", vout, target, satoshis),
+        Event::NoPuzzle { .. } => eprintln!("No 21e8 scripts found."),
+        Event::Loaded { txid } => {
+            println!("{GREEN}■{RESET_COLOR} Loaded {CYAN}{}{RESET_COLOR}", txid)
+        }
+        Event::ProofVerified { .. } => println!("{GREEN}■{RESET_COLOR} Merkle proof verified"),
+        Event::Verified { valid, .. } => match valid {
+            true => println!("valid"),
+            false => println!("invalid"),
+        },
+        Event::Threads { threads } => println!("{CYAN}[{} threads]{RESET_COLOR}\n", threads),
+        Event::Fee {
+            size,
+            fee_rate,
+            fee,
+            payout,
+        } => println!(
+            "{GREEN}■{RESET_COLOR} Fee: {} sats for {} bytes at {} sat/kB, paying out {} sats",
+            fee, size, fee_rate, payout
+        ),
+        Event::Solution {
+            target,
+            ephemeral_pubkey,
+            raw_tx,
+            ..
+        } => {
+            println!(
+                "\nSigned {GREEN}{}{RESET_COLOR} with {}\n",
+                target, ephemeral_pubkey
+            );
+            println!("{}{}{}\n", YELLOW, raw_tx, RESET_COLOR);
+        }
+        Event::Summary {
+            attempts,
+            elapsed_secs,
+            hashrate,
+            expected_attempts,
+            luck,
+            best_prefix,
+            best_hash,
+            lifetime_attempts,
+            lifetime_hashrate,
+            lifetime_luck,
+            ..
+        } => {
+            let format_luck = |luck: &Option<f64>| match luck {
+                Some(luck) => format!("{:.2}", luck),
+                None => String::from("-"),
+            };
+
+            println!(
+                "\n{CYAN}{} attempts in {:.1}s ({:.1} H/s), expected {:.0}, luck {}{RESET_COLOR}",
+                attempts,
+                elapsed_secs,
+                hashrate,
+                expected_attempts,
+                format_luck(luck)
+            );
+            println!(
+                "{CYAN}best share: {} ({} hex characters matched){RESET_COLOR}",
+                best_hash, best_prefix
+            );
+            println!(
+                "{CYAN}lifetime: {} attempts ({:.1} H/s), luck {}{RESET_COLOR}",
+                lifetime_attempts,
+                lifetime_hashrate,
+                format_luck(lifetime_luck)
+            );
+        }
+        Event::Broadcast {
+            success,
+            response,
+            path: Some(path),
+        } => match success {
+            true => println!("{}\t{}", path, response),
+            false => eprintln!("{}\t{}", path, response),
+        },
+        Event::Broadcast {
+            success, response, ..
+        } => match success {
+            true => println!("Success! {}", response),
+            false => eprintln!("Broadcast failed: {}", response),
+        },
+        Event::Saved { path } => println!("Saved to {}", path),
+        Event::Balance { address, satoshis } => println!("{}\t{}", address, satoshis),
+        Event::WalletUtxo {
+            txid,
+            vout,
+            address,
+            satoshis,
+            spent,
+        } => {
+            let state = match spent {
+                true => "spent",
+                false => "unspent",
+            };
+
+            println!("{}:{}\t{}\t{}\t{}", txid, vout, address, satoshis, state)
+        }
+        Event::ContentHash { sha256 } => println!("sha256 {}", sha256),
+        Event::Built { txid, size, fee } => {
+            println!("{}\t{} bytes, {} sats fee", txid, size, fee)
+        }
+        Event::RawTx { raw_tx, .. } => println!("{}", raw_tx),
+        Event::Error { message } => eprintln!("{}", message),
+        Event::Target { .. } | Event::Stats { .. } => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn envelope(event: &Event) -> serde_json::Value {
+        serde_json::to_value(Envelope {
+            schema: SCHEMA_VERSION,
+            timestamp: 0,
+            event,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn events_are_flattened_into_the_envelope() {
+        let value = envelope(&Event::Output {
+            vout: 1,
+            target: String::from("21e8"),
+            satoshis: 1000,
+        });
+
+        assert_eq!(
+            value,
+            json!({
+                "schema": SCHEMA_VERSION,
+                "timestamp": 0,
+                "event": "output",
+                "vout": 1,
+                "target": "21e8",
+                "satoshis": 1000,
+            })
+        );
+    }
+
+    #[test]
+    fn event_names_are_snake_case() {
+        let value = envelope(&Event::ProofVerified {
+            txid: String::from("aa"),
+        });
+        assert_eq!(value["event"], "proof_verified");
+
+        let value = envelope(&Event::NoPuzzle {
+            txid: String::from("aa"),
+        });
+        assert_eq!(value["event"], "no_puzzle");
+    }
+
+    #[test]
+    fn broadcast_path_is_only_present_when_set() {
+        let value = envelope(&Event::Broadcast {
+            success: true,
+            response: String::from("aa"),
+            path: None,
+        });
+        assert!(value.get("path").is_none());
+
+        let value = envelope(&Event::Broadcast {
+            success: false,
+            response: String::from("aa"),
+            path: Some(String::from("solved/aa.txt")),
+        });
+        assert_eq!(value["path"], "solved/aa.txt");
+        assert_eq!(value["success"], false);
+    }
+
+    #[test]
+    fn wallet_utxos_carry_their_state() {
+        let value = envelope(&Event::WalletUtxo {
+            txid: String::from("aa"),
+            vout: 2,
+            address: String::from("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"),
+            satoshis: 600,
+            spent: true,
+        });
+
+        assert_eq!(value["event"], "wallet_utxo");
+        assert_eq!(value["vout"], 2);
+        assert_eq!(value["spent"], true);
+    }
+
+    #[test]
+    fn built_jobs_carry_size_and_fee() {
+        let value = envelope(&Event::Built {
+            txid: String::from("aa"),
+            size: 249,
+            fee: 13,
+        });
+
+        assert_eq!(value["event"], "built");
+        assert_eq!(value["size"], 249);
+        assert_eq!(value["fee"], 13);
+    }
+
+    #[test]
+    fn raw_transactions_carry_their_txid() {
+        let value = envelope(&Event::RawTx {
+            txid: String::from("aa"),
+            raw_tx: String::from("0100"),
+        });
+
+        assert_eq!(value["event"], "raw_tx");
+        assert_eq!(value["txid"], "aa");
+        assert_eq!(value["raw_tx"], "0100");
+    }
+}
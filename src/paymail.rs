@@ -0,0 +1,606 @@
+use crate::{MinerError, Res};
+use bsv::{PublicKey, Script, Signature, ECDSA};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// bsvalias capability ids
+const PKI: &str = "pki";
+const P2P_PAYMENT_DESTINATION: &str = "2a40af698840";
+/// Advertised by hosts that always sign their P2P outputs
+const SIGNED_DESTINATION: &str = "signedDestination";
+
+/// How long discovered capabilities and public keys are trusted
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Deserialize)]
+struct WellKnown {
+    capabilities: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct PkiResponse {
+    pubkey: String,
+}
+
+#[derive(Deserialize)]
+struct P2POutput {
+    script: String,
+}
+
+#[derive(Deserialize)]
+struct P2PResponse {
+    outputs: Vec<P2POutput>,
+    #[serde(default)]
+    reference: String,
+    /// DER signature by the PKI key over the first output script
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+struct Cached<T> {
+    fetched: Instant,
+    value: T,
+}
+
+#[derive(Default)]
+struct PaymailCache {
+    capabilities: HashMap<String, Cached<HashMap<String, Value>>>,
+    pubkeys: HashMap<String, Cached<String>>,
+}
+
+fn cache() -> &'static Mutex<PaymailCache> {
+    static CACHE: OnceLock<Mutex<PaymailCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(PaymailCache::default()))
+}
+
+fn fresh<T: Clone>(entry: Option<&Cached<T>>) -> Option<T> {
+    entry
+        .filter(|cached| cached.fetched.elapsed() < CACHE_TTL)
+        .map(|cached| cached.value.clone())
+}
+
+/// A `alias@domain` Paymail, `$handle` and `1handle` being HandCash and RelayX shorthands
+#[derive(Debug, Clone)]
+pub struct Paymail {
+    pub alias: String,
+    pub domain: String,
+}
+
+/// Paymail output to pay, with the host's reference for the payment
+#[derive(Debug, Clone)]
+pub struct PaymailDestination {
+    pub script: Script,
+    pub reference: String,
+}
+
+impl Paymail {
+    pub fn parse(input: &str) -> Option<Paymail> {
+        let input = input.trim().to_lowercase();
+
+        let (alias, domain) = match input.split_once('@') {
+            Some((alias, domain)) => (alias.to_string(), domain.to_string()),
+            None => match input.chars().next() {
+                Some('$') => (input[1..].to_string(), String::from("handcash.io")),
+                Some('1') => (input[1..].to_string(), String::from("relayx.io")),
+                _ => return None,
+            },
+        };
+
+        match alias.is_empty() || domain.is_empty() {
+            true => None,
+            false => Some(Paymail { alias, domain }),
+        }
+    }
+
+    pub fn address(&self) -> String {
+        format!("{}@{}", self.alias, self.domain)
+    }
+
+    fn fill_template(&self, template: &str) -> String {
+        template
+            .replace("{alias}", &self.alias)
+            .replace("{domain.tld}", &self.domain)
+    }
+}
+
+/// Resolves Paymail payment destinations over `.well-known/bsvalias`
+pub struct PaymailClient {
+    client: reqwest::Client,
+    scheme: &'static str,
+    require_signature: bool,
+}
+
+impl Default for PaymailClient {
+    fn default() -> Self {
+        PaymailClient::new("https")
+    }
+}
+
+impl PaymailClient {
+    /// `http` lets a local stand-in act as the Paymail host
+    pub fn new(scheme: &'static str) -> Self {
+        PaymailClient {
+            client: reqwest::Client::new(),
+            scheme,
+            require_signature: true,
+        }
+    }
+
+    /// Unsigned outputs are refused unless this is turned off. Hosts advertising
+    /// signed destinations must sign either way.
+    pub fn require_signature(mut self, require_signature: bool) -> Self {
+        self.require_signature = require_signature;
+        self
+    }
+
+    pub async fn capabilities(&self, domain: &str) -> Res<HashMap<String, Value>> {
+        if let Ok(cache) = cache().lock() {
+            if let Some(capabilities) = fresh(cache.capabilities.get(domain)) {
+                return Ok(capabilities);
+            }
+        }
+
+        let url = format!("{}://{}/.well-known/bsvalias", self.scheme, domain);
+        let well_known = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<WellKnown>()
+            .await?;
+
+        if let Ok(mut cache) = cache().lock() {
+            cache.capabilities.insert(
+                domain.to_string(),
+                Cached {
+                    fetched: Instant::now(),
+                    value: well_known.capabilities.clone(),
+                },
+            );
+        }
+
+        Ok(well_known.capabilities)
+    }
+
+    async fn endpoint(&self, paymail: &Paymail, capability: &str) -> Res<String> {
+        let capabilities = self.capabilities(&paymail.domain).await?;
+
+        match capabilities.get(capability).and_then(Value::as_str) {
+            Some(template) => Ok(paymail.fill_template(template)),
+            None => Err(MinerError::Network(format!(
+                "{} does not support the {} capability",
+                paymail.domain, capability
+            ))),
+        }
+    }
+
+    pub async fn public_key(&self, paymail: &Paymail) -> Res<String> {
+        let address = paymail.address();
+
+        if let Ok(cache) = cache().lock() {
+            if let Some(pubkey) = fresh(cache.pubkeys.get(&address)) {
+                return Ok(pubkey);
+            }
+        }
+
+        let url = self.endpoint(paymail, PKI).await?;
+        let pki = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<PkiResponse>()
+            .await?;
+
+        PublicKey::from_hex(&pki.pubkey)?;
+
+        if let Ok(mut cache) = cache().lock() {
+            cache.pubkeys.insert(
+                address,
+                Cached {
+                    fetched: Instant::now(),
+                    value: pki.pubkey.clone(),
+                },
+            );
+        }
+
+        Ok(pki.pubkey)
+    }
+
+    /// Asks the host for a fresh P2P payment destination for `satoshis`
+    pub async fn destination(&self, paymail: &Paymail, satoshis: u64) -> Res<PaymailDestination> {
+        let url = self.endpoint(paymail, P2P_PAYMENT_DESTINATION).await?;
+
+        let response = self
+            .client
+            .post(url)
+            .json(&json!({ "satoshis": satoshis }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<P2PResponse>()
+            .await?;
+
+        if response.outputs.len() > 1 {
+            tracing::warn!(paymail = %paymail.address(), outputs = response.outputs.len(), "paying the first of several Paymail outputs");
+        }
+
+        let output = response.outputs.first().ok_or_else(|| {
+            MinerError::Network(format!("{} returned no outputs", paymail.address()))
+        })?;
+
+        let script_bytes = hex::decode(&output.script)?;
+
+        let signed_host = matches!(
+            self.capabilities(&paymail.domain)
+                .await?
+                .get(SIGNED_DESTINATION),
+            Some(Value::Bool(true))
+        );
+
+        let signature = match (&response.signature, signed_host || self.require_signature) {
+            (Some(signature), _) => Some(signature),
+            (None, true) => {
+                return Err(MinerError::Network(format!(
+                    "{} returned an unsigned output",
+                    paymail.address()
+                )))
+            }
+            (None, false) => {
+                tracing::warn!(paymail = %paymail.address(), "paying an unsigned Paymail output");
+                None
+            }
+        };
+
+        if let Some(signature) = signature {
+            let pubkey = PublicKey::from_hex(&self.public_key(paymail).await?)?;
+            let signature = Signature::from_der(&hex::decode(signature)?)?;
+
+            let verified = ECDSA::verify_digest(
+                &script_bytes,
+                &pubkey,
+                &signature,
+                bsv::SigningHash::Sha256d,
+            )?;
+
+            if !verified {
+                return Err(MinerError::Network(format!(
+                    "{} returned an output not signed by its public key",
+                    paymail.address()
+                )));
+            }
+        }
+
+        Ok(PaymailDestination {
+            script: Script::from_bytes(&script_bytes)?,
+            reference: response.reference,
+        })
+    }
+
+    pub async fn resolve(&self, input: &str, satoshis: u64) -> Res<PaymailDestination> {
+        let paymail = Paymail::parse(input)
+            .ok_or_else(|| MinerError::Parse(format!("{} is not a Paymail or handle", input)))?;
+
+        let destination = self.destination(&paymail, satoshis).await?;
+
+        tracing::info!(paymail = %paymail.address(), reference = %destination.reference, "Paymail destination found");
+
+        Ok(destination)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bsv::{PrivateKey, SigningHash};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const SCRIPT: &str = "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac";
+
+    /// Stands in for a Paymail host, answering by path prefix and counting requests
+    struct Host {
+        domain: String,
+        hits: Arc<Mutex<HashMap<String, usize>>>,
+    }
+
+    impl Host {
+        fn hits(&self, prefix: &str) -> usize {
+            self.hits.lock().unwrap().get(prefix).copied().unwrap_or(0)
+        }
+
+        fn paymail(&self) -> Paymail {
+            Paymail::parse(&format!("alice@{}", self.domain)).unwrap()
+        }
+    }
+
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+
+        loop {
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+
+            if n == 0 {
+                break;
+            }
+
+            request.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&request).to_string();
+
+            if let Some(end) = text.find("\r\n\r\n") {
+                let content_length = text
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        match name.eq_ignore_ascii_case("content-length") {
+                            true => value.trim().parse::<usize>().ok(),
+                            false => None,
+                        }
+                    })
+                    .unwrap_or(0);
+
+                if request.len() >= end + 4 + content_length {
+                    break;
+                }
+            }
+        }
+
+        String::from_utf8_lossy(&request).to_string()
+    }
+
+    /// `routes` gets the host's `ip:port` to fill in endpoint templates
+    async fn host(routes: impl FnOnce(&str) -> Vec<(&'static str, Value)>) -> Host {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let domain = listener.local_addr().unwrap().to_string();
+
+        // ports are reused between tests, so forget whatever an earlier host served
+        if let Ok(mut cache) = cache().lock() {
+            cache.capabilities.remove(&domain);
+            cache
+                .pubkeys
+                .retain(|address, _| !address.ends_with(&domain));
+        }
+
+        let routes: Vec<(&'static str, String)> = routes(&domain)
+            .into_iter()
+            .map(|(prefix, body)| (prefix, body.to_string()))
+            .collect();
+
+        let hits = Arc::new(Mutex::new(HashMap::new()));
+        let server_hits = hits.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let request = read_request(&mut socket).await;
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+                let response = match routes.iter().find(|(prefix, _)| path.starts_with(prefix)) {
+                    Some((prefix, body)) => {
+                        *server_hits
+                            .lock()
+                            .unwrap()
+                            .entry(prefix.to_string())
+                            .or_insert(0) += 1;
+
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            body.len(),
+                            body
+                        )
+                    }
+                    None => String::from(
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    ),
+                };
+
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        Host { domain, hits }
+    }
+
+    fn well_known(domain: &str, signed: bool) -> Value {
+        json!({
+            "bsvalias": "1.0",
+            "capabilities": {
+                "pki": format!("http://{}/pki/{{alias}}@{{domain.tld}}", domain),
+                "2a40af698840": format!("http://{}/p2p/{{alias}}@{{domain.tld}}", domain),
+                "signedDestination": signed,
+            }
+        })
+    }
+
+    fn sign(key: &PrivateKey, script: &str) -> String {
+        ECDSA::sign_with_deterministic_k(
+            key,
+            &hex::decode(script).unwrap(),
+            SigningHash::Sha256d,
+            false,
+        )
+        .unwrap()
+        .to_der_hex()
+    }
+
+    async fn signed_host(key: &PrivateKey, signature: Option<String>, signed: bool) -> Host {
+        let pubkey = key.to_public_key().unwrap().to_hex().unwrap();
+
+        host(|domain| {
+            vec![
+                ("/.well-known/bsvalias", well_known(domain, signed)),
+                (
+                    "/pki/",
+                    json!({ "bsvalias": "1.0", "handle": "alice", "pubkey": pubkey }),
+                ),
+                (
+                    "/p2p/",
+                    json!({
+                        "outputs": [{ "script": SCRIPT, "satoshis": 1000 }],
+                        "reference": "ref-1",
+                        "signature": signature,
+                    }),
+                ),
+            ]
+        })
+        .await
+    }
+
+    #[test]
+    fn parses_paymails_and_handles() {
+        let cases = [
+            ("alice@example.com", "alice", "example.com"),
+            (" Alice@Example.com ", "alice", "example.com"),
+            ("$alice", "alice", "handcash.io"),
+            ("1alice", "alice", "relayx.io"),
+        ];
+
+        for (input, alias, domain) in cases {
+            let paymail = Paymail::parse(input).unwrap();
+            assert_eq!(
+                (paymail.alias.as_str(), paymail.domain.as_str()),
+                (alias, domain)
+            );
+        }
+
+        for input in ["", "alice", "@example.com", "alice@", "$", "1"] {
+            assert!(Paymail::parse(input).is_none(), "{}", input);
+        }
+
+        let paymail = Paymail::parse("alice@example.com").unwrap();
+        assert_eq!(
+            paymail.fill_template("https://example.com/{alias}/{domain.tld}"),
+            "https://example.com/alice/example.com"
+        );
+    }
+
+    #[test]
+    fn cache_entries_expire() {
+        assert_eq!(fresh::<u8>(None), None);
+
+        let entry = Cached {
+            fetched: Instant::now(),
+            value: 1u8,
+        };
+        assert_eq!(fresh(Some(&entry)), Some(1));
+
+        if let Some(fetched) = Instant::now().checked_sub(CACHE_TTL + Duration::from_secs(1)) {
+            let entry = Cached {
+                fetched,
+                value: 1u8,
+            };
+            assert_eq!(fresh(Some(&entry)), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn discovers_capabilities_and_caches_them() {
+        let key = PrivateKey::from_random();
+        let host = signed_host(&key, None, false).await;
+        let client = PaymailClient::new("http");
+
+        let capabilities = client.capabilities(&host.domain).await.unwrap();
+        assert!(capabilities.contains_key(PKI));
+        assert!(capabilities.contains_key(P2P_PAYMENT_DESTINATION));
+
+        client.capabilities(&host.domain).await.unwrap();
+        assert_eq!(host.hits("/.well-known/bsvalias"), 1);
+
+        let pubkey = client.public_key(&host.paymail()).await.unwrap();
+        assert_eq!(pubkey, key.to_public_key().unwrap().to_hex().unwrap());
+
+        client.public_key(&host.paymail()).await.unwrap();
+        assert_eq!(host.hits("/pki/"), 1);
+        assert_eq!(host.hits("/.well-known/bsvalias"), 1);
+    }
+
+    #[tokio::test]
+    async fn accepts_correctly_signed_outputs() {
+        let key = PrivateKey::from_random();
+        let host = signed_host(&key, Some(sign(&key, SCRIPT)), true).await;
+
+        let destination = PaymailClient::new("http")
+            .resolve(&format!("alice@{}", host.domain), 1000)
+            .await
+            .unwrap();
+
+        assert_eq!(hex::encode(destination.script.to_bytes()), SCRIPT);
+        assert_eq!(destination.reference, "ref-1");
+        assert_eq!(host.hits("/p2p/"), 1);
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_signatures() {
+        let key = PrivateKey::from_random();
+        let other = PrivateKey::from_random();
+
+        // signed by a key other than the PKI key
+        let host = signed_host(&key, Some(sign(&other, SCRIPT)), false).await;
+        assert!(PaymailClient::new("http")
+            .require_signature(false)
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+
+        // signed over a different script
+        let host = signed_host(&key, Some(sign(&key, "6a")), false).await;
+        assert!(PaymailClient::new("http")
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+
+        let host = signed_host(&key, Some(String::from("3006")), false).await;
+        assert!(PaymailClient::new("http")
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn unsigned_outputs_need_an_opt_in() {
+        let key = PrivateKey::from_random();
+        let host = signed_host(&key, None, false).await;
+
+        assert!(PaymailClient::new("http")
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+
+        let destination = PaymailClient::new("http")
+            .require_signature(false)
+            .destination(&host.paymail(), 1000)
+            .await
+            .unwrap();
+        assert_eq!(hex::encode(destination.script.to_bytes()), SCRIPT);
+
+        // hosts that promise signatures must keep the promise
+        let host = signed_host(&key, None, true).await;
+        assert!(PaymailClient::new("http")
+            .require_signature(false)
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_capabilities_are_errors() {
+        let host = host(|_| vec![("/.well-known/bsvalias", json!({ "capabilities": {} }))]).await;
+
+        assert!(PaymailClient::new("http")
+            .destination(&host.paymail(), 1000)
+            .await
+            .is_err());
+        assert!(PaymailClient::new("http")
+            .public_key(&host.paymail())
+            .await
+            .is_err());
+    }
+}
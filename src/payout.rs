@@ -0,0 +1,308 @@
+use crate::{MinerError, Res, DUST_LIMIT};
+use bsv::Script;
+use serde::{Deserialize, Serialize};
+
+/// One recipient of a solved puzzle, as written in `Config.toml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutRule {
+    pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Share {
+    /// Percentage of what is left after fixed amounts
+    Percent(f64),
+    /// Fixed number of satoshis
+    Amount(u64),
+    /// Everything left over
+    Remainder,
+}
+
+#[derive(Debug, Clone)]
+pub struct Payout {
+    pub address: String,
+    pub script: Script,
+    pub share: Share,
+    /// Set when the address was derived from the configured xpub
+    pub derivation_path: Option<String>,
+}
+
+impl PayoutRule {
+    pub fn share(&self) -> Res<Share> {
+        match (self.percent, self.amount) {
+            (Some(percent), None) if percent > 0.0 && percent <= 100.0 => {
+                Ok(Share::Percent(percent))
+            }
+            (None, Some(amount)) => Ok(Share::Amount(amount)),
+            (None, None) => Ok(Share::Remainder),
+            _ => Err(MinerError::Config(format!(
+                "payout to {} needs either a percent between 0 and 100 or an amount",
+                self.address
+            ))),
+        }
+    }
+}
+
+impl Payout {
+    /// Pays the whole value to a single script
+    pub fn single(address: String, script: Script) -> Vec<Payout> {
+        vec![Payout {
+            address,
+            script,
+            share: Share::Remainder,
+            derivation_path: None,
+        }]
+    }
+
+    /// Splits `total` across `payouts` in order. Fixed amounts are paid first, percentages
+    /// share what is left rounded down, and any remainder goes to the first `Remainder`
+    /// recipient, else the first percentage recipient, else the first recipient.
+    pub fn split(payouts: &[Payout], total: u64) -> Res<Vec<u64>> {
+        if payouts.is_empty() {
+            return Err(MinerError::Config(String::from("no payout recipients")));
+        }
+
+        // amounts come straight from Config.toml, so they may not fit in a u64 together
+        let fixed = payouts
+            .iter()
+            .map(|p| match p.share {
+                Share::Amount(amount) => amount,
+                _ => 0,
+            })
+            .try_fold(0u64, u64::checked_add)
+            .ok_or_else(|| MinerError::Config(String::from("payout amounts overflow")))?;
+
+        let pool = total
+            .checked_sub(fixed)
+            .ok_or(MinerError::InsufficientValue {
+                value: total,
+                required: fixed,
+            })?;
+
+        let percent: f64 = payouts
+            .iter()
+            .map(|p| match p.share {
+                Share::Percent(percent) => percent,
+                _ => 0.0,
+            })
+            .sum();
+
+        // tolerate float noise from shares like 33.3 + 33.3 + 33.4
+        if percent > 100.0 + 1e-9 {
+            return Err(MinerError::Config(format!(
+                "payout percentages add up to {}%",
+                percent
+            )));
+        }
+
+        let mut amounts: Vec<u64> = payouts
+            .iter()
+            .map(|p| match p.share {
+                Share::Amount(amount) => amount,
+                Share::Percent(percent) => (pool as f64 * percent / 100.0).floor() as u64,
+                Share::Remainder => 0,
+            })
+            .collect();
+
+        let paid = amounts
+            .iter()
+            .try_fold(0u64, |sum, amount| sum.checked_add(*amount))
+            .ok_or_else(|| MinerError::Config(String::from("payout amounts overflow")))?;
+
+        let remainder = total.saturating_sub(paid);
+
+        let remainder_index = payouts
+            .iter()
+            .position(|p| p.share == Share::Remainder)
+            .or_else(|| {
+                payouts
+                    .iter()
+                    .position(|p| matches!(p.share, Share::Percent(_)))
+            })
+            .unwrap_or(0);
+
+        amounts[remainder_index] += remainder;
+
+        for (payout, amount) in payouts.iter().zip(&amounts) {
+            if *amount < DUST_LIMIT {
+                return Err(MinerError::InsufficientValue {
+                    value: *amount,
+                    required: DUST_LIMIT,
+                });
+            }
+
+            tracing::debug!(address = %payout.address, amount, "payout");
+        }
+
+        Ok(amounts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fee::placeholder_p2pkh;
+
+    fn payouts(shares: &[Share]) -> Vec<Payout> {
+        shares
+            .iter()
+            .enumerate()
+            .map(|(i, share)| Payout {
+                address: format!("recipient {}", i),
+                script: placeholder_p2pkh().unwrap(),
+                share: *share,
+                derivation_path: None,
+            })
+            .collect()
+    }
+
+    fn split(shares: &[Share], total: u64) -> Res<Vec<u64>> {
+        Payout::split(&payouts(shares), total)
+    }
+
+    #[test]
+    fn single_recipient_gets_everything() {
+        assert_eq!(split(&[Share::Remainder], 1234).unwrap(), vec![1234]);
+        assert_eq!(split(&[Share::Percent(100.0)], 1234).unwrap(), vec![1234]);
+        assert_eq!(split(&[Share::Amount(1000)], 1234).unwrap(), vec![1234]);
+    }
+
+    #[test]
+    fn amounts_come_before_percentages() {
+        let shares = [Share::Amount(100), Share::Percent(50.0), Share::Remainder];
+
+        // 50% of the 901 left after the fixed amount, rounded down
+        assert_eq!(split(&shares, 1001).unwrap(), vec![100, 450, 451]);
+    }
+
+    #[test]
+    fn remainder_goes_to_the_specified_recipient() {
+        let shares = [Share::Percent(25.0), Share::Remainder, Share::Percent(25.0)];
+        assert_eq!(split(&shares, 1003).unwrap(), vec![250, 503, 250]);
+
+        // without a remainder recipient the first percentage takes the rounding
+        let shares = [
+            Share::Amount(1),
+            Share::Percent(33.3),
+            Share::Percent(33.3),
+            Share::Percent(33.4),
+        ];
+        assert_eq!(split(&shares, 1002).unwrap(), vec![1, 334, 333, 334]);
+
+        // and without either the first recipient does
+        let shares = [Share::Amount(100), Share::Amount(200)];
+        assert_eq!(split(&shares, 350).unwrap(), vec![150, 200]);
+    }
+
+    #[test]
+    fn splits_sum_exactly_to_the_total() {
+        let mut seed = 0x21e8u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..2000 {
+            let total = 1000 + next() % 10_000_000;
+            let recipients = 1 + next() % 6;
+
+            let shares: Vec<Share> = (0..recipients)
+                .map(|_| match next() % 3 {
+                    0 => Share::Amount(1 + next() % 100),
+                    1 => Share::Percent((1 + next() % 1000) as f64 / 100.0),
+                    _ => Share::Remainder,
+                })
+                .collect();
+
+            match split(&shares, total) {
+                Ok(amounts) => {
+                    assert_eq!(amounts.iter().sum::<u64>(), total, "{:?}", shares);
+                    assert!(amounts.iter().all(|a| *a >= DUST_LIMIT));
+
+                    for (share, amount) in shares.iter().zip(&amounts) {
+                        if let Share::Amount(fixed) = share {
+                            assert!(amount >= fixed);
+                        }
+                    }
+                }
+                // only shares that round down to nothing may fail
+                Err(MinerError::InsufficientValue { value, .. }) => assert_eq!(value, 0),
+                Err(e) => panic!("unexpected error {} for {:?}", e, shares),
+            }
+        }
+    }
+
+    #[test]
+    fn dust_shares_are_rejected() {
+        // 0.01% of 100 satoshis rounds down to nothing
+        let shares = [Share::Remainder, Share::Percent(0.01)];
+        assert!(matches!(
+            split(&shares, 100),
+            Err(MinerError::InsufficientValue {
+                value: 0,
+                required: DUST_LIMIT
+            })
+        ));
+
+        // fixed amounts leave nothing for the remainder
+        let shares = [Share::Amount(100), Share::Remainder];
+        assert!(matches!(
+            split(&shares, 100),
+            Err(MinerError::InsufficientValue { value: 0, .. })
+        ));
+
+        assert!(matches!(
+            split(&[Share::Amount(0)], 0),
+            Err(MinerError::InsufficientValue { value: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn impossible_splits_are_rejected() {
+        assert!(matches!(split(&[], 100), Err(MinerError::Config(_))));
+
+        assert!(matches!(
+            split(&[Share::Amount(60), Share::Amount(50)], 100),
+            Err(MinerError::InsufficientValue {
+                value: 100,
+                required: 110
+            })
+        ));
+
+        assert!(matches!(
+            split(&[Share::Percent(60.0), Share::Percent(50.0)], 100),
+            Err(MinerError::Config(_))
+        ));
+
+        assert!(matches!(
+            split(&[Share::Amount(u64::MAX), Share::Amount(1)], u64::MAX),
+            Err(MinerError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn rules_parse_into_shares() {
+        let rule = |percent, amount| PayoutRule {
+            address: String::from("1abc"),
+            percent,
+            amount,
+        };
+
+        assert_eq!(rule(None, None).share().unwrap(), Share::Remainder);
+        assert_eq!(
+            rule(Some(12.5), None).share().unwrap(),
+            Share::Percent(12.5)
+        );
+        assert_eq!(rule(None, Some(500)).share().unwrap(), Share::Amount(500));
+
+        assert!(rule(Some(0.0), None).share().is_err());
+        assert!(rule(Some(100.1), None).share().is_err());
+        assert!(rule(Some(10.0), Some(500)).share().is_err());
+    }
+}
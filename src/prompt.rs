@@ -1,59 +1,142 @@
 use crate::config::Config;
-use crate::Res;
+use crate::{MinerError, Res};
 use bsv::PrivateKey;
 
 pub struct Prompt;
 
+/// Setup values given up front, anything left out is prompted for
+#[derive(Debug, Default, Clone, clap::Args)]
+pub struct SetupAnswers {
+    /// Enable Miner API
+    #[arg(long)]
+    pub miner_id: Option<bool>,
+    /// Miner ID private key in WIF format, empty generates a new one
+    #[arg(long)]
+    pub priv_key: Option<String>,
+    /// Message for Miner API
+    #[arg(long)]
+    pub message: Option<String>,
+    /// Pay solved puzzles out to a handle, Paymail, P2PKH address or script
+    #[arg(long)]
+    pub pay_to: Option<String>,
+    /// Automatically publish solved puzzles
+    #[arg(long)]
+    pub autopublish: Option<bool>,
+    /// Automatically write solved puzzles to a .txt file
+    #[arg(long)]
+    pub autosave: Option<bool>,
+}
+
 impl Prompt {
     pub fn run_setup() -> Res<Config> {
-        let enabled = asky::Confirm::new("Enable Miner API?").prompt()?;
+        Prompt::run_setup_with(SetupAnswers::default())
+    }
+
+    pub fn run_setup_with(answers: SetupAnswers) -> Res<Config> {
+        let enabled = match answers.miner_id {
+            Some(enabled) => enabled,
+            None => asky::Confirm::new("Enable Miner API?").prompt()?,
+        };
+
+        let priv_key = match answers.priv_key {
+            Some(priv_key) if priv_key.is_empty() => PrivateKey::from_random().to_wif()?,
+            Some(priv_key) => {
+                PrivateKey::from_wif(&priv_key)
+                    .map_err(|e| MinerError::Config(format!("invalid --priv-key: {}", e)))?;
+                priv_key
+            }
+            None => Prompt::priv_key()?,
+        };
+
+        let message = match answers.message {
+            Some(message) => message,
+            None => asky::Text::new("Select a message for Miner API").prompt()?,
+        };
+
+        let pay_to = match answers.pay_to {
+            Some(pay_to) => pay_to,
+            None => Prompt::pay_to()?,
+        };
+
+        let autopublish = match answers.autopublish {
+            Some(autopublish) => autopublish,
+            None => asky::Confirm::new("Automatically publish solved puzzles?").prompt()?,
+        };
+
+        let autosave = match answers.autosave {
+            Some(autosave) => autosave,
+            None => {
+                asky::Confirm::new("Automatically write solved puzzles to a .txt file?").prompt()?
+            }
+        };
+
+        // re-running setup must not drop payouts, keys or tuning it does not ask about
+        let existing = match Config::existing_config() {
+            true => Config::read_from_toml().unwrap_or_else(|e| {
+                tracing::warn!(path = Config::path(), error = %e, "replacing unreadable config");
+                Config::default()
+            }),
+            false => Config::default(),
+        };
+
+        let settings =
+            existing.with_setup(pay_to, autopublish, autosave, enabled, priv_key, message);
 
-        let mut priv_key: String;
+        Config::write_to_toml(settings.clone())?;
 
+        Ok(settings)
+    }
+
+    fn priv_key() -> Res<String> {
         loop {
-            priv_key = asky::Password::new(
+            let priv_key = asky::Password::new(
                 "Private key in WIF format (or press Enter to generate a new one)",
             )
             .prompt()?;
 
             if priv_key.is_empty() {
-                priv_key = PrivateKey::from_random().to_wif()?;
-                break;
+                return Ok(PrivateKey::from_random().to_wif()?);
             }
 
             match PrivateKey::from_wif(&priv_key) {
-                Ok(_) => break,
+                Ok(_) => return Ok(priv_key),
                 Err(e) => {
                     println!("{}\n", e);
                     continue;
                 }
             }
         }
+    }
 
-        let message = asky::Text::new("Select a message for Miner API").prompt()?;
-
-        let mut pay_to: String;
-
+    fn pay_to() -> Res<String> {
         loop {
-            pay_to = asky::Text::new(
-                "Pay solved puzzle out to (1handle, $handle, PayMail or p2pkh address)",
+            let pay_to = asky::Text::new(
+                "Pay solved puzzle out to (1handle, $handle, PayMail, p2pkh address or script)",
             )
             .prompt()?;
 
             if !pay_to.is_empty() {
-                break;
+                return Ok(pay_to);
             }
         }
+    }
+}
 
-        let autopublish = asky::Confirm::new("Automatically publish solved puzzles?").prompt()?;
-
-        let autosave =
-            asky::Confirm::new("Automatically write solved puzzles to a .txt file?").prompt()?;
-
-        let settings = Config::new(pay_to, autopublish, autosave, enabled, priv_key, message);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Config::write_to_toml(settings.clone());
+    #[test]
+    fn invalid_keys_fail_before_anything_is_written() {
+        let answers = SetupAnswers {
+            miner_id: Some(true),
+            priv_key: Some(String::from("not a key")),
+            ..SetupAnswers::default()
+        };
 
-        Ok(settings)
+        match Prompt::run_setup_with(answers) {
+            Err(MinerError::Config(message)) => assert!(message.starts_with("invalid --priv-key")),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
     }
 }
@@ -0,0 +1,420 @@
+use crate::fee::FeeBreakdown;
+use crate::{Funding, MagicMiner, MinerError, Res};
+use bsv::{Hash, Script, Transaction, TxOut};
+use std::path::Path;
+
+/// B:// protocol prefix
+pub const B_PREFIX: &str = "19HxigV4QyBv3tHpQVcUEQyq1pzZVdoAut";
+
+/// Something to ask proof of work for, hashed into the job and optionally stored on chain
+#[derive(Debug, Clone)]
+pub struct Content {
+    pub data: Vec<u8>,
+    pub media_type: String,
+    pub encoding: String,
+    pub filename: Option<String>,
+}
+
+fn media_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Path part of `url`, without scheme, host, query or fragment
+fn url_path(url: &str) -> &str {
+    let url = url.split(['?', '#']).next().unwrap_or(url);
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+
+    url.split_once('/').map_or("", |(_, path)| path)
+}
+
+/// Last path segment of `url`, `None` for a bare host or a trailing slash
+fn url_filename(url: &str) -> Option<String> {
+    url_path(url)
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+}
+
+impl Content {
+    pub fn from_text(text: &str) -> Content {
+        Content {
+            data: text.as_bytes().to_vec(),
+            media_type: String::from("text/plain"),
+            encoding: String::from("utf-8"),
+            filename: None,
+        }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Res<Content> {
+        let path = path.as_ref();
+
+        Ok(Content {
+            data: std::fs::read(path)?,
+            media_type: media_type(path).to_string(),
+            encoding: String::from("binary"),
+            filename: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(String::from),
+        })
+    }
+
+    /// Downloads a copy of `url` to hash, the same bytes anyone fetching it should get
+    pub async fn fetch(url: &str) -> Res<Content> {
+        let response = reqwest::Client::new()
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let media_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+            .unwrap_or_else(|| media_type(Path::new(url_path(url))).to_string());
+
+        Ok(Content {
+            data: response.bytes().await?.to_vec(),
+            media_type,
+            encoding: String::from("binary"),
+            filename: url_filename(url),
+        })
+    }
+
+    /// Points at content stored elsewhere instead of embedding it
+    pub fn reference(reference: &str) -> Content {
+        Content {
+            data: reference.as_bytes().to_vec(),
+            media_type: String::from("text/uri-list"),
+            encoding: String::from("utf-8"),
+            filename: None,
+        }
+    }
+
+    pub fn hash(&self) -> Vec<u8> {
+        Hash::sha_256(&self.data).to_bytes()
+    }
+
+    /// OP_FALSE OP_RETURN output carrying the content with the B:// protocol
+    pub fn b_output(&self) -> Res<TxOut> {
+        let mut chunks = vec![
+            vec![0],   // OP_FALSE
+            vec![106], // OP_RETURN
+            Script::encode_pushdata(B_PREFIX.as_bytes())?,
+            Script::encode_pushdata(&self.data)?,
+            Script::encode_pushdata(self.media_type.as_bytes())?,
+            Script::encode_pushdata(self.encoding.as_bytes())?,
+        ];
+
+        if let Some(filename) = &self.filename {
+            chunks.push(Script::encode_pushdata(filename.as_bytes())?);
+        }
+
+        Ok(TxOut::new(0u64, &Script::from_chunks(chunks)?))
+    }
+}
+
+/// Builds and funds transactions that publish new 21e8 jobs
+pub struct Publisher;
+
+impl Publisher {
+    /// 21e8 output for `hash`, `target` being the hex that follows the `21e8` prefix
+    pub fn job_output(hash: &[u8], target: &[u8], satoshis: u64) -> Res<TxOut> {
+        let mut full_target = vec![0x21, 0xe8];
+        full_target.extend_from_slice(target);
+
+        let script = MagicMiner::puzzle_script(hash, &full_target)?;
+
+        Ok(TxOut::new(satoshis, &script))
+    }
+
+    /// Spends `funding` into `outputs`, with change back to the funding address
+    pub fn build(
+        funding: &Funding,
+        outputs: &[TxOut],
+        fee_rate: u64,
+    ) -> Res<(Transaction, FeeBreakdown)> {
+        // amounts come from --satoshis, so they may not fit in a u64 together
+        let spent = outputs
+            .iter()
+            .try_fold(0u64, |sum, o| sum.checked_add(o.get_satoshis()))
+            .ok_or_else(|| MinerError::Parse(String::from("output amounts overflow")))?;
+
+        let available =
+            funding
+                .satoshis
+                .checked_sub(spent)
+                .ok_or(MinerError::InsufficientValue {
+                    value: funding.satoshis,
+                    required: spent,
+                })?;
+
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&funding.tx_in());
+
+        for output in outputs {
+            tx.add_output(output);
+        }
+
+        // change is filled in once the size, and so the fee, is known
+        tx.add_output(&TxOut::new(0u64, &funding.script));
+
+        let fees = FeeBreakdown::estimate(&tx, available, fee_rate)?;
+
+        tx.set_output(outputs.len(), &TxOut::new(fees.payout, &funding.script));
+
+        funding.sign(&mut tx, 0)?;
+
+        Ok((tx, fees))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: [u8; 32] = [0xab; 32];
+
+    /// Push of up to 0xffff bytes the way a minimal encoder writes it
+    fn push(data: &[u8]) -> Vec<u8> {
+        let mut bytes = match data.len() {
+            len if len < 76 => vec![len as u8],
+            len if len <= 0xff => vec![0x4c, len as u8],
+            len => vec![0x4d, len as u8, (len >> 8) as u8],
+        };
+
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn script_bytes(output: &TxOut) -> Vec<u8> {
+        output.get_script_pub_key().to_bytes()
+    }
+
+    #[test]
+    fn b_output_for_short_text() {
+        let output = Content::from_text("hi").b_output().unwrap();
+
+        let mut expected = vec![0x00, 0x6a, 0x22];
+        expected.extend_from_slice(B_PREFIX.as_bytes());
+        expected.extend_from_slice(&[0x02, b'h', b'i']);
+        expected.push(0x0a);
+        expected.extend_from_slice(b"text/plain");
+        expected.push(0x05);
+        expected.extend_from_slice(b"utf-8");
+
+        assert_eq!(output.get_satoshis(), 0);
+        assert_eq!(script_bytes(&output), expected);
+    }
+
+    #[test]
+    fn b_output_uses_pushdata_for_long_payloads() {
+        for (len, header) in [
+            (75, vec![0x4b]),
+            (76, vec![0x4c, 0x4c]),
+            (100, vec![0x4c, 0x64]),
+            (255, vec![0x4c, 0xff]),
+            (256, vec![0x4d, 0x00, 0x01]),
+            (300, vec![0x4d, 0x2c, 0x01]),
+        ] {
+            let text = "a".repeat(len);
+            let bytes = script_bytes(&Content::from_text(&text).b_output().unwrap());
+
+            let mut expected = vec![0x00, 0x6a];
+            expected.extend(push(B_PREFIX.as_bytes()));
+            expected.extend_from_slice(&header);
+            expected.extend_from_slice(text.as_bytes());
+            expected.extend(push(b"text/plain"));
+            expected.extend(push(b"utf-8"));
+
+            assert_eq!(bytes, expected, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn file_content_carries_its_type_and_name() {
+        let dir = std::env::temp_dir().join(format!("magic_miner_content_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("Note.TXT");
+        std::fs::write(&path, "abc").unwrap();
+
+        let content = Content::from_file(&path).unwrap();
+
+        assert_eq!(content.media_type, "text/plain");
+        assert_eq!(content.encoding, "binary");
+        assert_eq!(content.filename.as_deref(), Some("Note.TXT"));
+        assert_eq!(
+            hex::encode(content.hash()),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let mut expected = vec![0x00, 0x6a];
+        expected.extend(push(B_PREFIX.as_bytes()));
+        expected.extend(push(b"abc"));
+        expected.extend(push(b"text/plain"));
+        expected.extend(push(b"binary"));
+        expected.extend(push(b"Note.TXT"));
+
+        assert_eq!(script_bytes(&content.b_output().unwrap()), expected);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn guesses_media_types_from_extensions() {
+        let cases = [
+            ("a.md", "text/markdown"),
+            ("a.HTM", "text/html"),
+            ("a.jpeg", "image/jpeg"),
+            ("a.svg", "image/svg+xml"),
+            ("a", "application/octet-stream"),
+            ("a.xyz", "application/octet-stream"),
+        ];
+
+        for (path, expected) in cases {
+            assert_eq!(media_type(Path::new(path)), expected, "{}", path);
+        }
+
+        let reference = Content::reference("https://example.com/a.png");
+        assert_eq!(reference.media_type, "text/uri-list");
+        assert_eq!(reference.data, b"https://example.com/a.png");
+    }
+
+    #[test]
+    fn urls_name_content_by_their_last_path_segment() {
+        let cases = [
+            ("https://example.com/files/a.png", Some("a.png")),
+            ("https://example.com/files/a.png?size=2#top", Some("a.png")),
+            ("https://example.com/files/", None),
+            ("https://example.com/?q=a.png", None),
+            ("https://example.com", None),
+            ("example.com/a.txt", Some("a.txt")),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(url_filename(url).as_deref(), expected, "{}", url);
+        }
+
+        assert_eq!(
+            media_type(Path::new(url_path("https://example.com/a.PNG?v=1"))),
+            "image/png"
+        );
+    }
+
+    fn funding(satoshis: u64) -> Funding {
+        let mut funding = Funding::placeholder().unwrap();
+        funding.satoshis = satoshis;
+        funding
+    }
+
+    #[test]
+    fn job_output_is_a_21e8_puzzle() {
+        let output = Publisher::job_output(&HASH, &[0x00], 1_000).unwrap();
+
+        assert_eq!(output.get_satoshis(), 1_000);
+        assert_eq!(
+            hex::encode(output.get_script_pub_key().to_bytes()),
+            format!("20{}0321e800825479a87c7f758875ac", hex::encode(HASH))
+        );
+
+        assert!(Publisher::job_output(&HASH[..31], &[0x00], 1_000).is_err());
+    }
+
+    #[test]
+    fn change_goes_back_to_the_funding_address() {
+        let funding = funding(10_000);
+        let outputs = vec![
+            Publisher::job_output(&HASH, &[0x00], 1_000).unwrap(),
+            Content::from_text("hi").b_output().unwrap(),
+        ];
+
+        let (tx, fees) = Publisher::build(&funding, &outputs, 1000).unwrap();
+
+        assert_eq!(tx.get_ninputs(), 1);
+        assert_eq!(tx.get_noutputs(), 3);
+        assert_eq!(fees.value, 9_000);
+        assert_eq!(fees.fee, fees.size as u64);
+        assert_eq!(fees.payout, 9_000 - fees.fee);
+
+        let change = tx.get_output(2).unwrap();
+        assert_eq!(change.get_satoshis(), fees.payout);
+        assert_eq!(
+            change.get_script_pub_key().to_bytes(),
+            funding.script.to_bytes()
+        );
+
+        let total: u64 = (0..tx.get_noutputs())
+            .filter_map(|i| tx.get_output(i))
+            .map(|o| o.get_satoshis())
+            .sum();
+        assert_eq!(total + fees.fee, funding.satoshis);
+
+        // the funding input is signed
+        assert!(!tx
+            .get_input(0)
+            .unwrap()
+            .get_unlocking_script()
+            .to_bytes()
+            .is_empty());
+    }
+
+    #[test]
+    fn sizes_the_fee_for_a_signed_input() {
+        let outputs = vec![Publisher::job_output(&HASH, &[0x00], 1_000).unwrap()];
+
+        let (_, fees) = Publisher::build(&funding(10_000), &outputs, 1000).unwrap();
+
+        // overhead, signed input, 47 byte puzzle output and P2PKH change
+        assert_eq!(fees.size, 10 + 149 + (9 + 47) + 34);
+        assert_eq!(fees.fee, 249);
+        assert_eq!(fees.payout, 8_751);
+    }
+
+    #[test]
+    fn refuses_funding_below_outputs_and_fee() {
+        let outputs = vec![Publisher::job_output(&HASH, &[0x00], 1_000).unwrap()];
+
+        match Publisher::build(&funding(999), &outputs, 50) {
+            Err(MinerError::InsufficientValue { value, required }) => {
+                assert_eq!((value, required), (999, 1_000));
+            }
+            other => panic!("unexpected result {:?}", other.map(|(_, fees)| fees.fee)),
+        }
+
+        assert!(matches!(
+            Publisher::build(&funding(1_005), &outputs, 50),
+            Err(MinerError::InsufficientValue { .. })
+        ));
+
+        let outputs = vec![
+            Publisher::job_output(&HASH, &[0x00], u64::MAX).unwrap(),
+            Publisher::job_output(&HASH, &[0x00], 1).unwrap(),
+        ];
+        assert!(matches!(
+            Publisher::build(&funding(10_000), &outputs, 50),
+            Err(MinerError::Parse(_))
+        ));
+    }
+}
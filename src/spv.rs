@@ -0,0 +1,258 @@
+use crate::{MinerError, Res};
+use bsv::Hash;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Merkle proof in the TSC format returned by WhatsOnChain
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MerkleProof {
+    pub index: u64,
+    pub tx_or_id: String,
+    pub target: String,
+    pub nodes: Vec<String>,
+}
+
+/// Block headers supplied locally, keyed by block hash
+pub struct HeaderStore {
+    headers: HashMap<String, Vec<u8>>,
+}
+
+fn reversed_hex_bytes(s: &str) -> Res<Vec<u8>> {
+    let mut bytes = hex::decode(s.trim())?;
+    bytes.reverse();
+    Ok(bytes)
+}
+
+impl MerkleProof {
+    pub async fn fetch(txid: &str) -> Res<MerkleProof> {
+        let url = format!(
+            "https://api.whatsonchain.com/v1/bsv/main/tx/{}/proof/tsc",
+            txid
+        );
+        let proofs = reqwest::Client::new()
+            .get(url)
+            .send()
+            .await?
+            .json::<Vec<MerkleProof>>()
+            .await?;
+
+        match proofs.into_iter().next() {
+            Some(proof) => Ok(proof),
+            None => Err(MinerError::Network(format!(
+                "no merkle proof found for {}",
+                txid
+            ))),
+        }
+    }
+
+    /// Merkle root in internal byte order
+    pub fn compute_root(&self, txid: &str) -> Res<Vec<u8>> {
+        let mut current = reversed_hex_bytes(txid)?;
+        let mut index = self.index;
+
+        for node in &self.nodes {
+            let sibling = match node.as_str() {
+                "*" => current.clone(),
+                hash => reversed_hex_bytes(hash)?,
+            };
+
+            if sibling.len() != 32 {
+                return Err(MinerError::Parse(format!("invalid merkle node {}", node)));
+            }
+
+            let concat = match index & 1 {
+                1 => [sibling, current].concat(),
+                _ => [current, sibling].concat(),
+            };
+
+            current = Hash::sha_256d(&concat).to_bytes();
+            index >>= 1;
+        }
+
+        Ok(current)
+    }
+
+    pub fn verify(&self, txid: &str, headers: &HeaderStore) -> Res<()> {
+        if self.tx_or_id.len() == 64 && !self.tx_or_id.eq_ignore_ascii_case(txid) {
+            return Err(MinerError::MerkleProof(format!(
+                "proof is for {}",
+                self.tx_or_id
+            )));
+        }
+
+        let merkle_root = match headers.merkle_root(&self.target) {
+            Some(root) => root,
+            None => {
+                return Err(MinerError::Config(format!(
+                    "block header {} not found",
+                    self.target
+                )))
+            }
+        };
+
+        if self.compute_root(txid)? != merkle_root {
+            return Err(MinerError::MerkleProof(format!(
+                "root does not match block header {}",
+                self.target
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl HeaderStore {
+    /// Reads one hex-encoded 80 byte header per line
+    pub fn from_file(path: &str) -> Res<HeaderStore> {
+        let content = std::fs::read_to_string(path)?;
+        let mut headers = HashMap::new();
+
+        for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let header = hex::decode(line)?;
+
+            if header.len() != 80 {
+                return Err(MinerError::Parse(format!("invalid block header: {}", line)));
+            }
+
+            let mut block_hash = Hash::sha_256d(&header).to_bytes();
+            block_hash.reverse();
+
+            headers.insert(hex::encode(block_hash), header);
+        }
+
+        Ok(HeaderStore { headers })
+    }
+
+    pub fn merkle_root(&self, block_hash: &str) -> Option<Vec<u8>> {
+        self.headers
+            .get(&block_hash.to_lowercase())
+            .map(|header| header[36..68].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // block 100000
+    const TXIDS: [&str; 4] = [
+        "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+        "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+        "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+        "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+    ];
+    const LEFT_BRANCH: &str = "ccdafb73d8dcd0173d5d5c3c9a0770d0b3953db889dab99ef05b1907518cb815";
+    const MERKLE_ROOT: &str = "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766";
+
+    const GENESIS_HEADER: &str = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    const GENESIS_HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+    const GENESIS_COINBASE: &str =
+        "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    fn proof(index: u64, target: &str, nodes: &[&str]) -> MerkleProof {
+        MerkleProof {
+            index,
+            tx_or_id: String::new(),
+            target: target.to_string(),
+            nodes: nodes.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+
+    fn display_hex(mut bytes: Vec<u8>) -> String {
+        bytes.reverse();
+        hex::encode(bytes)
+    }
+
+    fn store_with_root(block_hash: &str, root: &str) -> HeaderStore {
+        let mut header = vec![0u8; 80];
+        header[36..68].copy_from_slice(&reversed_hex_bytes(root).unwrap());
+
+        HeaderStore {
+            headers: HashMap::from([(block_hash.to_string(), header)]),
+        }
+    }
+
+    #[test]
+    fn single_transaction_root_is_its_txid() {
+        let root = proof(0, "", &[]).compute_root(GENESIS_COINBASE).unwrap();
+        assert_eq!(display_hex(root), GENESIS_COINBASE);
+    }
+
+    #[test]
+    fn right_and_left_siblings_are_ordered_by_index() {
+        let root = proof(2, "", &[TXIDS[3], LEFT_BRANCH])
+            .compute_root(TXIDS[2])
+            .unwrap();
+        assert_eq!(display_hex(root), MERKLE_ROOT);
+
+        let root = proof(3, "", &[TXIDS[2], LEFT_BRANCH])
+            .compute_root(TXIDS[3])
+            .unwrap();
+        assert_eq!(display_hex(root), MERKLE_ROOT);
+    }
+
+    #[test]
+    fn star_duplicates_the_current_hash() {
+        let root = proof(2, "", &["*", LEFT_BRANCH])
+            .compute_root(TXIDS[2])
+            .unwrap();
+        assert_eq!(
+            display_hex(root),
+            "fa435470825de273081dcc706b25514c936fa6dc80ab965ce6970d68ddd0b553"
+        );
+    }
+
+    #[test]
+    fn short_nodes_are_rejected() {
+        assert!(proof(0, "", &["abcd"]).compute_root(TXIDS[0]).is_err());
+        assert!(proof(0, "", &["zz"]).compute_root(TXIDS[0]).is_err());
+    }
+
+    #[test]
+    fn header_store_reads_genesis() {
+        let path = std::env::temp_dir().join("magic_miner_spv_headers.txt");
+        std::fs::write(&path, format!("{}\n\n", GENESIS_HEADER)).unwrap();
+
+        let headers = HeaderStore::from_file(path.to_str().unwrap()).unwrap();
+        let root = headers.merkle_root(GENESIS_HASH).unwrap();
+
+        assert_eq!(display_hex(root), GENESIS_COINBASE);
+        assert!(proof(0, GENESIS_HASH, &[])
+            .verify(GENESIS_COINBASE, &headers)
+            .is_ok());
+
+        std::fs::write(&path, "0100").unwrap();
+        assert!(HeaderStore::from_file(path.to_str().unwrap()).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn verify_checks_target_txid_and_root() {
+        let headers = store_with_root("aa", MERKLE_ROOT);
+
+        assert!(proof(2, "aa", &[TXIDS[3], LEFT_BRANCH])
+            .verify(TXIDS[2], &headers)
+            .is_ok());
+
+        // wrong position in the block
+        assert!(matches!(
+            proof(3, "aa", &[TXIDS[3], LEFT_BRANCH]).verify(TXIDS[2], &headers),
+            Err(MinerError::MerkleProof(_))
+        ));
+
+        // unknown block
+        assert!(proof(2, "bb", &[TXIDS[3], LEFT_BRANCH])
+            .verify(TXIDS[2], &headers)
+            .is_err());
+
+        // proof for another transaction
+        let mut other = proof(2, "aa", &[TXIDS[3], LEFT_BRANCH]);
+        other.tx_or_id = TXIDS[0].to_string();
+        assert!(matches!(
+            other.verify(TXIDS[2], &headers),
+            Err(MinerError::MerkleProof(_))
+        ));
+    }
+}
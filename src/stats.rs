@@ -0,0 +1,512 @@
+use crate::Res;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const LIFETIME_STATS_PATH: &str = "stats.json";
+const JOBS_FOLDER: &str = "jobs";
+
+/// Shares needed in a histogram bucket before it is trusted for estimating work
+const MIN_SHARES: u64 = 16;
+
+/// Live counters shared between the mining threads and whoever is watching them
+pub struct MiningStats {
+    started: Instant,
+    target_len: usize,
+    attempts: Vec<AtomicU64>,
+    /// Lock-free copy of the best prefix, so most hashes skip the lock
+    best_prefix: AtomicUsize,
+    /// Best prefix and its hash, always updated together
+    best: Mutex<(usize, Vec<u8>)>,
+    /// Hashes counted by how many leading hex characters matched the target
+    histogram: Vec<AtomicU64>,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+/// End-of-job report built from `MiningStats` once `mine_target` returns
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSummary {
+    pub solved: bool,
+    pub attempts: u64,
+    pub elapsed_secs: f64,
+    pub hashrate: f64,
+    pub expected_attempts: f64,
+    /// Expected over actual attempts, above 1 means luckier than average. Only set for solved jobs
+    pub luck: Option<f64>,
+    pub best_prefix: usize,
+    pub best_hash: String,
+    pub histogram: Vec<u64>,
+    /// Work implied by the near-miss histogram alone
+    pub estimated_attempts: f64,
+}
+
+/// Every session spent on one puzzle, saved in `jobs/<txid>_<vout>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRecord {
+    pub txid: String,
+    pub vout: usize,
+    pub target: String,
+    pub sessions: Vec<serde_json::Value>,
+}
+
+/// Totals for one payout identity, accumulated across sessions
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LifetimeStats {
+    pub jobs: u64,
+    pub solved: u64,
+    pub attempts: u64,
+    pub mining_secs: f64,
+    /// Sum of expected attempts over solved jobs
+    pub expected_attempts: f64,
+}
+
+/// Number of leading hex characters `hash` shares with `target`
+pub fn matched_nibbles(hash: &[u8], target: &[u8]) -> usize {
+    let mut nibbles = 0;
+
+    for (h, t) in hash.iter().zip(target) {
+        if h == t {
+            nibbles += 2;
+            continue;
+        }
+
+        if h >> 4 == t >> 4 {
+            nibbles += 1;
+        }
+
+        break;
+    }
+
+    nibbles
+}
+
+impl MiningStats {
+    pub fn new(threads: usize, target: &[u8]) -> Self {
+        MiningStats {
+            started: Instant::now(),
+            target_len: target.len(),
+            attempts: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+            best_prefix: AtomicUsize::new(0),
+            best: Mutex::new((0, Vec::new())),
+            histogram: (0..=target.len() * 2).map(|_| AtomicU64::new(0)).collect(),
+            paused: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.attempts.len()
+    }
+
+    pub fn record(&self, thread: usize, hash: &[u8], target: &[u8]) {
+        self.attempts[thread].fetch_add(1, Ordering::Relaxed);
+
+        let matched = matched_nibbles(hash, target);
+        self.histogram[matched].fetch_add(1, Ordering::Relaxed);
+
+        if matched <= self.best_prefix.load(Ordering::Relaxed) {
+            return;
+        }
+
+        if let Ok(mut best) = self.best.lock() {
+            if matched > best.0 {
+                *best = (matched, hash.to_vec());
+                self.best_prefix.store(matched, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+            .iter()
+            .map(|a| a.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn thread_attempts(&self) -> Vec<u64> {
+        self.attempts
+            .iter()
+            .map(|a| a.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Best prefix match so far, in hex characters
+    pub fn best_prefix(&self) -> usize {
+        self.best_prefix.load(Ordering::Relaxed)
+    }
+
+    /// Hash with the longest prefix match so far
+    pub fn best_hash(&self) -> Vec<u8> {
+        self.best_share().1
+    }
+
+    /// Best prefix and the hash that matched it, read together
+    pub fn best_share(&self) -> (usize, Vec<u8>) {
+        self.best
+            .lock()
+            .map(|best| best.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn histogram(&self) -> Vec<u64> {
+        self.histogram
+            .iter()
+            .map(|h| h.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Each extra matched hex character is 16 times rarer, so the deepest bucket with
+    /// enough shares gives an estimate of the work done that doesn't rely on counters
+    pub fn estimated_attempts(&self) -> f64 {
+        let histogram = self.histogram();
+        let mut at_least = 0;
+
+        for (nibbles, count) in histogram.iter().enumerate().rev() {
+            at_least += count;
+
+            if at_least >= MIN_SHARES {
+                return at_least as f64 * 16f64.powi(nibbles as i32);
+            }
+        }
+
+        at_least as f64
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn hashrate(&self) -> f64 {
+        match self.elapsed().as_secs_f64() {
+            secs if secs > 0.0 => self.attempts() as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Expected number of attempts to meet the target
+    pub fn expected_attempts(&self) -> f64 {
+        256f64.powi(self.target_len as i32)
+    }
+
+    /// Finding a solution is memoryless, so the ETA never depends on time already spent
+    pub fn eta(&self) -> Option<Duration> {
+        match self.hashrate() {
+            rate if rate > 0.0 => Some(Duration::from_secs_f64(self.expected_attempts() / rate)),
+            _ => None,
+        }
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl JobSummary {
+    pub fn new(stats: &MiningStats, solved: bool) -> Self {
+        let attempts = stats.attempts();
+        let expected_attempts = stats.expected_attempts();
+        let (best_prefix, best_hash) = stats.best_share();
+
+        JobSummary {
+            solved,
+            attempts,
+            elapsed_secs: stats.elapsed().as_secs_f64(),
+            hashrate: stats.hashrate(),
+            expected_attempts,
+            luck: match solved && attempts > 0 {
+                true => Some(expected_attempts / attempts as f64),
+                false => None,
+            },
+            best_prefix,
+            best_hash: hex::encode(best_hash),
+            histogram: stats.histogram(),
+            estimated_attempts: stats.estimated_attempts(),
+        }
+    }
+
+    /// Appends this session to the job's record on disk and returns the file path
+    pub fn save(&self, txid: &str, vout: usize, target: &[u8]) -> Res<String> {
+        self.save_in(JOBS_FOLDER, txid, vout, target)
+    }
+
+    pub fn save_in(&self, folder: &str, txid: &str, vout: usize, target: &[u8]) -> Res<String> {
+        if !std::path::Path::new(folder).exists() {
+            std::fs::create_dir_all(folder)?;
+        }
+
+        let path = format!("{}/{}_{}.json", folder, txid, vout);
+
+        let mut record = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str::<JobRecord>(&content)?,
+            Err(_) => JobRecord {
+                txid: txid.to_string(),
+                vout,
+                target: hex::encode(target),
+                sessions: Vec::new(),
+            },
+        };
+
+        record.sessions.push(serde_json::to_value(self)?);
+
+        std::fs::write(&path, serde_json::to_string_pretty(&record)?)?;
+
+        Ok(path)
+    }
+}
+
+impl LifetimeStats {
+    fn read_all() -> Res<BTreeMap<String, LifetimeStats>> {
+        match std::fs::read_to_string(LIFETIME_STATS_PATH) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn load(user: &str) -> Res<LifetimeStats> {
+        Ok(LifetimeStats::read_all()?.remove(user).unwrap_or_default())
+    }
+
+    /// Adds a finished job to the user's totals and returns the updated totals
+    pub fn record(user: &str, summary: &JobSummary) -> Res<LifetimeStats> {
+        let mut all = LifetimeStats::read_all()?;
+        let lifetime = all.entry(user.to_string()).or_default();
+
+        lifetime.jobs += 1;
+        lifetime.attempts += summary.attempts;
+        lifetime.mining_secs += summary.elapsed_secs;
+
+        if summary.solved {
+            lifetime.solved += 1;
+            lifetime.expected_attempts += summary.expected_attempts;
+        }
+
+        let updated = lifetime.clone();
+
+        let tmp_path = format!("{}.tmp", LIFETIME_STATS_PATH);
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(&all)?)?;
+        std::fs::rename(tmp_path, LIFETIME_STATS_PATH)?;
+
+        Ok(updated)
+    }
+
+    pub fn hashrate(&self) -> f64 {
+        match self.mining_secs {
+            secs if secs > 0.0 => self.attempts as f64 / secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Expected over actual attempts across every job, abandoned ones included
+    pub fn luck(&self) -> Option<f64> {
+        match self.attempts {
+            0 => None,
+            attempts => Some(self.expected_attempts / attempts as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats_with_attempts(target: &[u8], attempts: u64) -> MiningStats {
+        let stats = MiningStats::new(1, target);
+
+        for _ in 0..attempts {
+            stats.record(0, &[0x00], target);
+        }
+
+        stats
+    }
+
+    #[test]
+    fn counts_matched_nibbles() {
+        let target = [0x21, 0xe8, 0xab];
+
+        assert_eq!(matched_nibbles(&[0x00, 0xe8, 0xab], &target), 0);
+        assert_eq!(matched_nibbles(&[0x20, 0xe8, 0xab], &target), 1);
+        assert_eq!(matched_nibbles(&[0x21, 0x00, 0xab], &target), 2);
+        assert_eq!(matched_nibbles(&[0x21, 0xe0, 0xab], &target), 3);
+        assert_eq!(matched_nibbles(&[0x21, 0xe8, 0xa0], &target), 5);
+        assert_eq!(matched_nibbles(&[0x21, 0xe8, 0xab, 0xff], &target), 6);
+        assert_eq!(matched_nibbles(&[0x21], &target), 2);
+        assert_eq!(matched_nibbles(&[], &target), 0);
+    }
+
+    #[test]
+    fn records_histogram_and_best_share() {
+        let target = [0x21, 0xe8];
+        let stats = MiningStats::new(2, &target);
+
+        stats.record(0, &[0x00, 0x00], &target);
+        stats.record(1, &[0x21, 0xe0], &target);
+        stats.record(0, &[0x20, 0x00], &target);
+        // a tie keeps the first hash
+        stats.record(1, &[0x21, 0xe1], &target);
+
+        assert_eq!(stats.attempts(), 4);
+        assert_eq!(stats.thread_attempts(), vec![2, 2]);
+        assert_eq!(stats.histogram(), vec![1, 1, 0, 2, 0]);
+        assert_eq!(stats.best_prefix(), 3);
+        assert_eq!(stats.best_share(), (3, vec![0x21, 0xe0]));
+    }
+
+    #[test]
+    fn best_share_stays_consistent_across_threads() {
+        let target = [0x21, 0xe8, 0xab, 0xcd];
+        let stats = std::sync::Arc::new(MiningStats::new(8, &target));
+
+        let handles: Vec<_> = (0..8)
+            .map(|thread| {
+                let stats = stats.clone();
+
+                std::thread::spawn(move || {
+                    for i in 0..2000usize {
+                        // every thread walks through prefixes in a different order
+                        let len = (i * 7 + thread * 3) % target.len();
+                        let mut hash = target[..len].to_vec();
+                        hash.push(!target[len]);
+                        hash.push(thread as u8);
+
+                        stats.record(thread, &hash, &target);
+
+                        let (prefix, best_hash) = stats.best_share();
+                        assert_eq!(matched_nibbles(&best_hash, &target), prefix);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let (prefix, best_hash) = stats.best_share();
+        assert_eq!(prefix, 6);
+        assert_eq!(stats.best_prefix(), prefix);
+        assert_eq!(matched_nibbles(&best_hash, &target), prefix);
+    }
+
+    #[test]
+    fn estimates_attempts_from_the_deepest_trusted_bucket() {
+        let target = [0x21, 0xe8];
+        let stats = MiningStats::new(1, &target);
+
+        assert_eq!(stats.estimated_attempts(), 0.0);
+
+        // too few shares anywhere falls back to the raw count
+        for _ in 0..10 {
+            stats.record(0, &[0x00, 0x00], &target);
+        }
+        assert_eq!(stats.estimated_attempts(), 10.0);
+
+        // 16 hashes matching at least one hex character imply 16 * 16 attempts
+        for _ in 0..12 {
+            stats.record(0, &[0x20, 0x00], &target);
+        }
+        for _ in 0..4 {
+            stats.record(0, &[0x21, 0x00], &target);
+        }
+        assert_eq!(stats.estimated_attempts(), 256.0);
+
+        for _ in 0..16 {
+            stats.record(0, &[0x21, 0x00], &target);
+        }
+        assert_eq!(stats.estimated_attempts(), 20.0 * 256.0);
+    }
+
+    #[test]
+    fn saves_every_session_of_a_job() {
+        let folder = std::env::temp_dir().join("magic_miner_stats_jobs");
+        let _ = std::fs::remove_dir_all(&folder);
+        let folder = folder.to_str().unwrap();
+
+        let target = [0x21, 0xe8];
+        let stats = stats_with_attempts(&target, 3);
+        stats.record(0, &[0x21, 0x00], &target);
+        let summary = JobSummary::new(&stats, false);
+
+        let path = summary.save_in(folder, "aa", 1, &target).unwrap();
+        assert_eq!(path, format!("{}/aa_1.json", folder));
+        assert_eq!(summary.save_in(folder, "aa", 1, &target).unwrap(), path);
+
+        let record: JobRecord =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+        assert_eq!(record.txid, "aa");
+        assert_eq!(record.vout, 1);
+        assert_eq!(record.target, "21e8");
+        assert_eq!(record.sessions.len(), 2);
+        assert_eq!(record.sessions[0]["attempts"], 4);
+        assert_eq!(record.sessions[0]["best_prefix"], 2);
+        assert_eq!(record.sessions[0]["best_hash"], "2100");
+        assert_eq!(
+            record.sessions[0]["histogram"],
+            serde_json::json!([3, 0, 1, 0, 0])
+        );
+
+        let _ = std::fs::remove_dir_all(folder);
+    }
+
+    #[test]
+    fn expected_attempts_grow_with_the_target() {
+        assert_eq!(MiningStats::new(1, &[]).expected_attempts(), 1.0);
+        assert_eq!(MiningStats::new(1, &[0x21]).expected_attempts(), 256.0);
+        assert_eq!(
+            MiningStats::new(1, &[0x21, 0xe8]).expected_attempts(),
+            65536.0
+        );
+    }
+
+    #[test]
+    fn luck_is_only_set_for_solved_jobs() {
+        let stats = stats_with_attempts(&[0x21], 512);
+
+        let solved = JobSummary::new(&stats, true);
+        assert_eq!(solved.attempts, 512);
+        assert_eq!(solved.luck, Some(0.5));
+
+        assert_eq!(JobSummary::new(&stats, false).luck, None);
+        assert_eq!(
+            JobSummary::new(&MiningStats::new(1, &[0x21]), true).luck,
+            None
+        );
+    }
+
+    #[test]
+    fn lifetime_luck_includes_abandoned_work() {
+        assert_eq!(LifetimeStats::default().luck(), None);
+        assert_eq!(LifetimeStats::default().hashrate(), 0.0);
+
+        let lifetime = LifetimeStats {
+            jobs: 2,
+            solved: 1,
+            attempts: 1024,
+            mining_secs: 4.0,
+            expected_attempts: 256.0,
+        };
+
+        assert_eq!(lifetime.luck(), Some(0.25));
+        assert_eq!(lifetime.hashrate(), 256.0);
+    }
+}
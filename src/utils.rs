@@ -1,6 +1,11 @@
-use std::{fs::OpenOptions, io::Write, sync::OnceLock};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    sync::OnceLock,
+};
 
-use crate::Res;
+use crate::{MinerError, Res};
+use bsv::Transaction;
 
 fn hex_lookup() -> &'static [bool; 256] {
     static HEX_LOOKUP: OnceLock<[bool; 256]> = OnceLock::new();
@@ -15,12 +20,61 @@ fn hex_lookup() -> &'static [bool; 256] {
 
 pub fn is_valid_txid(txid: &str) -> bool {
     if txid.len() != 64 {
-        println!("not 64 char");
         return false;
     }
     txid.bytes().all(|byte| hex_lookup()[byte as usize])
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outpoint {
+    pub txid: String,
+    pub vout: Option<usize>,
+}
+
+/// Accepts a bare txid, `txid:vout`, `txid_vout` or a block explorer URL containing a txid
+pub fn parse_outpoint(input: &str) -> Option<Outpoint> {
+    let input = input.trim();
+
+    if input.is_empty() {
+        return None;
+    }
+
+    let segments: Vec<&str> = input
+        .split(|c| matches!(c, '/' | '?' | '#' | '&' | '='))
+        .filter(|s| !s.is_empty())
+        .collect();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let (txid, vout) = match segment.split_once(|c| c == ':' || c == '_' || c == '.') {
+            Some((txid, vout)) => (txid, Some(vout)),
+            None => (*segment, None),
+        };
+
+        if !is_valid_txid(txid) {
+            continue;
+        }
+
+        let vout = match vout {
+            // an explicit index must be one, never fall back to detecting the output
+            Some(vout) => Some(vout.parse::<usize>().ok()?),
+            None => segments.get(i + 1).and_then(|v| v.parse::<usize>().ok()),
+        };
+
+        return Some(Outpoint {
+            txid: txid.to_lowercase(),
+            vout,
+        });
+    }
+
+    None
+}
+
+pub fn reverse_txid(txid: &str) -> Res<String> {
+    let mut bytes = hex::decode(txid)?;
+    bytes.reverse();
+    Ok(hex::encode(bytes))
+}
+
 pub fn find_next_suffix(name: &str, folder_path: &str) -> usize {
     let mut suffix = 1;
 
@@ -34,9 +88,11 @@ pub fn find_next_suffix(name: &str, folder_path: &str) -> usize {
     suffix
 }
 
-pub fn write_to_file(txid: &str, raw_tx: &str) -> Res<()> {
-    let folder_path = "solved";
+pub fn write_to_file(txid: &str, raw_tx: &str) -> Res<String> {
+    write_to_folder("solved", txid, raw_tx)
+}
 
+pub fn write_to_folder(folder_path: &str, txid: &str, raw_tx: &str) -> Res<String> {
     if !std::path::Path::new(folder_path).exists() {
         std::fs::create_dir(folder_path)?;
     }
@@ -52,9 +108,168 @@ pub fn write_to_file(txid: &str, raw_tx: &str) -> Res<()> {
     let mut file = OpenOptions::new()
         .write(true)
         .create(true)
-        .open(file_path)?;
+        .open(&file_path)?;
 
     file.write_all(raw_tx.as_bytes())?;
 
-    Ok(())
+    Ok(file_path)
+}
+
+/// Writes `metadata` next to a saved solution, as `<name>.json`
+pub fn write_metadata(solution_path: &str, metadata: &serde_json::Value) -> Res<String> {
+    let path = std::path::Path::new(solution_path).with_extension("json");
+    std::fs::write(&path, serde_json::to_string_pretty(metadata)?)?;
+    Ok(path.display().to_string())
+}
+
+/// Accepts raw hex, a path to a file containing raw hex, or `-` for stdin
+pub fn read_raw_tx(input: &str) -> Res<String> {
+    let raw = match input {
+        "-" => {
+            let mut buf = String::new();
+            std::io::stdin().read_to_string(&mut buf)?;
+            buf
+        }
+        path if std::path::Path::new(path).is_file() => std::fs::read_to_string(path)?,
+        hex => hex.to_string(),
+    };
+
+    let raw = raw.trim().to_string();
+
+    if raw.is_empty() || raw.len() % 2 != 0 || !raw.bytes().all(|b| hex_lookup()[b as usize]) {
+        return Err(MinerError::Parse(String::from(
+            "input is not a raw transaction hex",
+        )));
+    }
+
+    Ok(raw)
+}
+
+pub fn read_tx(input: &str) -> Res<Transaction> {
+    Ok(Transaction::from_hex(&read_raw_tx(input)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+    fn outpoint(vout: Option<usize>) -> Option<Outpoint> {
+        Some(Outpoint {
+            txid: TXID.to_string(),
+            vout,
+        })
+    }
+
+    #[test]
+    fn parses_outpoints() {
+        let cases = [
+            (TXID.to_string(), outpoint(None)),
+            (format!("  {}\n", TXID), outpoint(None)),
+            (TXID.to_uppercase(), outpoint(None)),
+            (format!("{}:0", TXID), outpoint(Some(0))),
+            (format!("{}:12", TXID), outpoint(Some(12))),
+            (format!("{}_3", TXID), outpoint(Some(3))),
+            (format!("{}.1", TXID), outpoint(Some(1))),
+            (
+                format!("https://whatsonchain.com/tx/{}", TXID),
+                outpoint(None),
+            ),
+            (
+                format!("https://whatsonchain.com/tx/{}/2", TXID),
+                outpoint(Some(2)),
+            ),
+            (
+                format!("https://whatsonchain.com/tx/{}?tab=outputs#o5", TXID),
+                outpoint(None),
+            ),
+            (
+                format!("https://example.com/explorer?tx={}", TXID),
+                outpoint(None),
+            ),
+            (
+                format!("https://example.com/o/{}_7", TXID),
+                outpoint(Some(7)),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse_outpoint(&input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_non_outpoints() {
+        let cases = [
+            String::new(),
+            String::from("   "),
+            String::from("21e8"),
+            TXID[1..].to_string(),
+            format!("{}0", TXID),
+            TXID.replace('a', "g"),
+            format!("{}:x", TXID),
+            format!("{}_foo", TXID),
+            format!("{}:-1", TXID),
+            format!("{}:", TXID),
+            format!("https://example.com/o/{}_7x", TXID),
+            String::from("https://whatsonchain.com/tx/"),
+        ];
+
+        for input in cases {
+            assert_eq!(parse_outpoint(&input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn validates_and_reverses_txids() {
+        assert!(is_valid_txid(TXID));
+        assert!(is_valid_txid(&TXID.to_uppercase()));
+        assert!(!is_valid_txid(&TXID[2..]));
+        assert!(!is_valid_txid(&format!("{}zz", &TXID[2..])));
+
+        assert_eq!(
+            reverse_txid(TXID).unwrap(),
+            "3ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a"
+        );
+        assert_eq!(reverse_txid(&reverse_txid(TXID).unwrap()).unwrap(), TXID);
+        assert!(reverse_txid("abc").is_err());
+    }
+
+    #[test]
+    fn reads_raw_hex_from_files() {
+        let path = std::env::temp_dir().join("magic_miner_utils_raw_tx.hex");
+        std::fs::write(&path, "0100abcd\n").unwrap();
+
+        assert_eq!(read_raw_tx(path.to_str().unwrap()).unwrap(), "0100abcd");
+
+        std::fs::write(&path, "not hex").unwrap();
+        assert!(read_raw_tx(path.to_str().unwrap()).is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn parses_raw_transactions() {
+        let mut tx = Transaction::new(1, 0);
+        tx.add_input(&bsv::TxIn::new(
+            &[1u8; 32],
+            0,
+            &bsv::Script::default(),
+            None,
+        ));
+
+        let parsed = read_tx(&tx.to_hex().unwrap()).unwrap();
+        assert_eq!(parsed.get_id_hex().unwrap(), tx.get_id_hex().unwrap());
+
+        assert!(read_tx("00").is_err());
+    }
+
+    #[test]
+    fn reads_raw_hex() {
+        assert_eq!(read_raw_tx(" 0100abCD \n").unwrap(), "0100abCD");
+        assert!(read_raw_tx("").is_err());
+        assert!(read_raw_tx("010").is_err());
+        assert!(read_raw_tx("01 00").is_err());
+    }
 }
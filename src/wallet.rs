@@ -0,0 +1,322 @@
+use crate::fee::FeeBreakdown;
+use crate::funding::sign_p2pkh;
+use crate::{Funding, MinerError, Res, DUST_LIMIT};
+use bsv::{ExtendedPrivateKey, P2PKHAddress, PrivateKey, Script, Transaction, TxIn, TxOut};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Unspent {
+    tx_hash: String,
+    tx_pos: u32,
+}
+
+/// A payout output created by one of our solved transactions
+#[derive(Debug, Clone)]
+pub struct PayoutUtxo {
+    pub txid: String,
+    pub vout: u32,
+    pub address: String,
+    pub satoshis: u64,
+    pub derivation_path: Option<String>,
+    pub spent: bool,
+}
+
+impl PayoutUtxo {
+    fn is_p2pkh(&self) -> bool {
+        P2PKHAddress::from_string(&self.address).is_ok()
+    }
+}
+
+/// Tracks mining income from the metadata saved next to solutions
+pub struct Wallet {
+    pub utxos: Vec<PayoutUtxo>,
+}
+
+impl Wallet {
+    /// Reads every solution metadata file in `dir`
+    pub fn load<P: AsRef<Path>>(dir: P) -> Res<Wallet> {
+        let mut utxos = Vec::new();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            let metadata: Value = match serde_json::from_str(&std::fs::read_to_string(&path)?) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    tracing::warn!(file = %path.display(), error = %e, "skipping invalid metadata");
+                    continue;
+                }
+            };
+
+            let txid = metadata["txid"].as_str().unwrap_or_default().to_string();
+            let payouts = metadata["payouts"].as_array().cloned().unwrap_or_default();
+
+            for (i, payout) in payouts.iter().enumerate() {
+                // older metadata has no vout, where payouts were always the first outputs
+                let vout = payout["vout"].as_u64().unwrap_or(i as u64);
+
+                utxos.push(PayoutUtxo {
+                    txid: txid.clone(),
+                    vout: vout as u32,
+                    address: payout["address"].as_str().unwrap_or_default().to_string(),
+                    satoshis: payout["satoshis"].as_u64().unwrap_or_default(),
+                    derivation_path: payout["derivation_path"].as_str().map(String::from),
+                    spent: false,
+                });
+            }
+        }
+
+        Ok(Wallet { utxos })
+    }
+
+    /// Marks outputs no longer in their address's unspent set as spent
+    pub async fn refresh(&mut self) -> Res<()> {
+        let addresses: HashSet<String> = self
+            .utxos
+            .iter()
+            .filter(|utxo| utxo.is_p2pkh())
+            .map(|utxo| utxo.address.clone())
+            .collect();
+
+        let mut unspent = HashSet::new();
+
+        for address in addresses {
+            let url = format!(
+                "https://api.whatsonchain.com/v1/bsv/main/address/{}/unspent",
+                address
+            );
+
+            let outputs = reqwest::Client::new()
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Vec<Unspent>>()
+                .await?;
+
+            unspent.extend(outputs.into_iter().map(|o| (o.tx_hash, o.tx_pos)));
+        }
+
+        self.mark_spent(&unspent);
+
+        Ok(())
+    }
+
+    /// Only P2PKH payouts can be looked up by address, the rest keep their state
+    fn mark_spent(&mut self, unspent: &HashSet<(String, u32)>) {
+        for utxo in self.utxos.iter_mut().filter(|utxo| utxo.is_p2pkh()) {
+            utxo.spent = !unspent.contains(&(utxo.txid.clone(), utxo.vout));
+        }
+    }
+
+    /// Unspent satoshis per address
+    pub fn balances(&self) -> BTreeMap<String, u64> {
+        let mut balances = BTreeMap::new();
+
+        for utxo in self.utxos.iter().filter(|utxo| !utxo.spent) {
+            *balances.entry(utxo.address.clone()).or_insert(0) += utxo.satoshis;
+        }
+
+        balances
+    }
+
+    /// Key for `utxo` from a WIF or an xprv, `None` when it does not own the output
+    fn key_for(utxo: &PayoutUtxo, payout_key: &str) -> Res<Option<PrivateKey>> {
+        let key = match (&utxo.derivation_path, payout_key.trim().starts_with("xprv")) {
+            (Some(path), true) => ExtendedPrivateKey::from_string(payout_key.trim())?
+                .derive_from_path(path)?
+                .get_private_key(),
+            _ => Funding::key(payout_key)?,
+        };
+
+        let address = P2PKHAddress::from_pubkey(&key.to_public_key()?)?.to_string()?;
+
+        match address == utxo.address {
+            true => Ok(Some(key)),
+            false => Ok(None),
+        }
+    }
+
+    /// Spends every unspent payout owned by `payout_key` to `to` in one transaction
+    pub fn sweep(&self, payout_key: &str, to: &Script, fee_rate: u64) -> Res<Transaction> {
+        let mut owned = Vec::new();
+
+        for utxo in self.utxos.iter().filter(|utxo| !utxo.spent) {
+            match Wallet::key_for(utxo, payout_key)? {
+                Some(key) => owned.push((utxo, key)),
+                None => tracing::warn!(address = %utxo.address, "no key for payout, skipping"),
+            }
+        }
+
+        if owned.is_empty() {
+            return Err(MinerError::InsufficientValue {
+                value: 0,
+                required: DUST_LIMIT,
+            });
+        }
+
+        let mut tx = Transaction::new(1, 0);
+        let mut total = 0u64;
+
+        for (utxo, key) in &owned {
+            let mut tx_in = TxIn::default();
+
+            tx_in.set_satoshis(utxo.satoshis);
+            tx_in.set_locking_script(
+                &P2PKHAddress::from_pubkey(&key.to_public_key()?)?.get_locking_script()?,
+            );
+            tx_in.set_prev_tx_id(&hex::decode(&utxo.txid)?);
+            tx_in.set_vout(utxo.vout);
+
+            tx.add_input(&tx_in);
+            total += utxo.satoshis;
+        }
+
+        tx.add_output(&TxOut::new(0u64, to));
+
+        let fees = FeeBreakdown::estimate(&tx, total, fee_rate)?;
+
+        tracing::info!(
+            inputs = owned.len(),
+            size = fees.size,
+            fee = fees.fee,
+            "sweep"
+        );
+
+        tx.set_output(0, &TxOut::new(fees.payout, to));
+
+        for (i, (utxo, key)) in owned.iter().enumerate() {
+            sign_p2pkh(&mut tx, i, key, utxo.satoshis)?;
+        }
+
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TXID: &str = "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098";
+    const ADDRESS: &str = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+    const PUBKEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    fn utxo(vout: u32, address: &str, spent: bool) -> PayoutUtxo {
+        PayoutUtxo {
+            txid: TXID.to_string(),
+            vout,
+            address: address.to_string(),
+            satoshis: 1_000,
+            derivation_path: None,
+            spent,
+        }
+    }
+
+    #[test]
+    fn refresh_leaves_non_p2pkh_payouts_alone() {
+        let mut wallet = Wallet {
+            utxos: vec![
+                utxo(0, ADDRESS, false),
+                utxo(1, ADDRESS, false),
+                utxo(2, PUBKEY, false),
+                utxo(3, "alice@example.com", true),
+            ],
+        };
+
+        let unspent = HashSet::from([(TXID.to_string(), 1)]);
+        wallet.mark_spent(&unspent);
+
+        let spent: Vec<bool> = wallet.utxos.iter().map(|utxo| utxo.spent).collect();
+        assert_eq!(spent, vec![true, false, false, true]);
+
+        let balances = wallet.balances();
+        assert_eq!(balances.get(ADDRESS), Some(&1_000));
+        assert_eq!(balances.get(PUBKEY), Some(&1_000));
+        assert_eq!(balances.get("alice@example.com"), None);
+    }
+
+    #[test]
+    fn sweeps_only_owned_unspent_payouts() {
+        // private key 1
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        let owned = "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH";
+
+        let wallet = Wallet {
+            utxos: vec![
+                utxo(0, owned, false),
+                utxo(1, owned, true),
+                utxo(2, ADDRESS, false),
+            ],
+        };
+
+        let to = P2PKHAddress::from_string(ADDRESS)
+            .unwrap()
+            .get_locking_script()
+            .unwrap();
+
+        let tx = wallet.sweep(wif, &to, 50).unwrap();
+
+        assert_eq!(tx.get_ninputs(), 1);
+        assert_eq!(tx.get_noutputs(), 1);
+        assert_eq!(tx.get_input(0).unwrap().get_vout(), 0);
+        // one signed input and one P2PKH output, 193 bytes at 50 sat/kB
+        assert_eq!(tx.get_output(0).unwrap().get_satoshis(), 1_000 - 10);
+
+        let nothing = Wallet {
+            utxos: vec![utxo(2, ADDRESS, false)],
+        };
+        assert!(matches!(
+            nothing.sweep(wif, &to, 50),
+            Err(MinerError::InsufficientValue { .. })
+        ));
+    }
+
+    #[test]
+    fn loads_payouts_with_their_output_index() {
+        let dir = std::env::temp_dir().join(format!("magic_miner_wallet_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let metadata = json!({
+            "txid": TXID,
+            "payouts": [
+                { "address": ADDRESS, "satoshis": 600, "vout": 2, "derivation_path": "m/0/4" },
+                { "address": PUBKEY, "satoshis": 400, "vout": 0 },
+            ],
+        });
+        std::fs::write(dir.join("solution.json"), metadata.to_string()).unwrap();
+
+        // metadata from before vout was saved
+        let legacy = json!({
+            "txid": TXID.replace('0', "1"),
+            "payouts": [{ "address": ADDRESS, "satoshis": 900 }],
+        });
+        std::fs::write(dir.join("legacy.json"), legacy.to_string()).unwrap();
+
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        std::fs::write(dir.join("solution.hex"), "00").unwrap();
+
+        let mut utxos = Wallet::load(&dir).unwrap().utxos;
+        utxos.sort_by_key(|utxo| (utxo.txid.clone(), utxo.vout));
+
+        let loaded: Vec<(u32, u64, Option<&str>)> = utxos
+            .iter()
+            .map(|utxo| (utxo.vout, utxo.satoshis, utxo.derivation_path.as_deref()))
+            .collect();
+
+        assert_eq!(
+            loaded,
+            vec![(0, 400, None), (2, 600, Some("m/0/4")), (0, 900, None)]
+        );
+        assert!(utxos.iter().all(|utxo| !utxo.spent));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
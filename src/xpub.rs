@@ -0,0 +1,229 @@
+use crate::{MinerError, Res};
+use bsv::{ExtendedPublicKey, P2PKHAddress, Script};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Next unused index per xpub and path
+pub const XPUB_INDEX_PATH: &str = "xpub_index.json";
+
+/// Indexes handed out by this process, ahead of the ones saved once a solution is signed
+fn reserved() -> &'static Mutex<BTreeMap<String, u32>> {
+    static RESERVED: OnceLock<Mutex<BTreeMap<String, u32>>> = OnceLock::new();
+    RESERVED.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+/// Derives a fresh receive address per solved puzzle from an extended public key
+pub struct XpubWallet {
+    xpub: String,
+    path: String,
+    index_path: PathBuf,
+}
+
+/// Address derived for one solution
+pub struct DerivedAddress {
+    pub address: String,
+    pub script: Script,
+    /// Full derivation path, e.g. `m/0/12`
+    pub path: String,
+}
+
+impl XpubWallet {
+    pub fn new(xpub: &str, path: &str) -> Res<XpubWallet> {
+        let path = path.trim().trim_end_matches('/').to_string();
+
+        if path.contains('\'') || path.contains('h') {
+            return Err(MinerError::Config(format!(
+                "{} has hardened steps, which cannot be derived from an xpub",
+                path
+            )));
+        }
+
+        ExtendedPublicKey::from_string(xpub.trim())?;
+
+        Ok(XpubWallet {
+            xpub: xpub.trim().to_string(),
+            path,
+            index_path: PathBuf::from(XPUB_INDEX_PATH),
+        })
+    }
+
+    /// Keeps the used indexes somewhere other than `xpub_index.json`
+    pub fn index_file<P: AsRef<Path>>(mut self, index_path: P) -> Self {
+        self.index_path = index_path.as_ref().to_path_buf();
+        self
+    }
+
+    fn key(&self) -> String {
+        format!("{}/{}", self.xpub, self.path)
+    }
+
+    fn reserved_key(&self) -> String {
+        format!("{}#{}", self.index_path.display(), self.key())
+    }
+
+    fn load_indexes(&self) -> Res<BTreeMap<String, u32>> {
+        match std::fs::read_to_string(&self.index_path) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save_indexes(&self, indexes: &BTreeMap<String, u32>) -> Res<()> {
+        let tmp_path = self.index_path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(indexes)?)?;
+        std::fs::rename(tmp_path, &self.index_path)?;
+        Ok(())
+    }
+
+    pub fn derive(&self, index: u32) -> Res<DerivedAddress> {
+        let path = format!("{}/{}", self.path, index);
+
+        let public_key = ExtendedPublicKey::from_string(&self.xpub)?
+            .derive_from_path(&path)?
+            .get_public_key();
+
+        let address = P2PKHAddress::from_pubkey(&public_key)?;
+
+        Ok(DerivedAddress {
+            address: address.to_string()?,
+            script: address.get_locking_script()?,
+            path,
+        })
+    }
+
+    /// Reserves the next index in memory only, so jobs that are cancelled or fail leave
+    /// no gap on disk. `commit` saves it once the solution is signed.
+    pub fn next_address(&self) -> Res<DerivedAddress> {
+        let saved = self.load_indexes()?.get(&self.key()).copied().unwrap_or(0);
+
+        let index = {
+            let mut reserved = reserved()
+                .lock()
+                .map_err(|_| MinerError::Config(String::from("xpub reservations poisoned")))?;
+
+            let next = reserved.entry(self.reserved_key()).or_insert(0);
+            let index = saved.max(*next);
+            *next = index + 1;
+            index
+        };
+
+        let derived = self.derive(index)?;
+
+        tracing::info!(address = %derived.address, path = %derived.path, "derived payout address");
+
+        Ok(derived)
+    }
+
+    /// Marks the index at the end of `path` as used on disk
+    pub fn commit(&self, path: &str) -> Res<()> {
+        let index: u32 = path.rsplit('/').next().unwrap_or_default().parse()?;
+
+        let mut indexes = self.load_indexes()?;
+        let next = indexes.entry(self.key()).or_insert(0);
+
+        if index + 1 > *next {
+            *next = index + 1;
+            self.save_indexes(&indexes)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // BIP32 test vector 2, master key
+    const XPUB: &str = "xpub661MyMwAqRbcFW31YEwpkMuc5THy2PSt5bDMsktWQcFF8syAmRUapSCGu8ED9W6oDMSgv6Zz8idoc4a6mr8BDzTJY47LJhkJ8UB7WEGuduB";
+    // its m/0 child, also from the test vector
+    const XPUB_M_0: &str = "xpub69H7F5d8KSRgmmdJg2KhpAK8SR3DjMwAdkxj3ZuxV27CprR9LgpeyGmXUbC6wb7ERfvrnKZjXoUmmDznezpbZb7ap6r1D3tgFxHmwMkQTPH";
+
+    const ADDRESSES: [&str; 3] = [
+        "1NyMg76BQxDvV6vRsQugNS4ED2hpZCJtwK",
+        "1NK8BtPi1AkfCwa4uuCb8fEtyYUg4NFC93",
+        "12tjDdbjc8cdSbKtzzkgad1aySbv8fuZjs",
+    ];
+
+    fn index_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("magic_miner_xpub_{}.json", name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn derives_test_vector_addresses() {
+        let wallet = XpubWallet::new(XPUB, "m/0/").unwrap();
+
+        for (index, address) in ADDRESSES.iter().enumerate() {
+            let derived = wallet.derive(index as u32).unwrap();
+
+            assert_eq!(derived.address, *address);
+            assert_eq!(derived.path, format!("m/0/{}", index));
+        }
+
+        let derived = wallet.derive(1).unwrap();
+        assert_eq!(
+            hex::encode(derived.script.to_bytes()),
+            "76a914e9c820f5fe7e6442ace0403f12ef8315198bb4e288ac"
+        );
+
+        // deriving from the child xpub gives the same addresses
+        let child = XpubWallet::new(XPUB_M_0, "m").unwrap();
+        assert_eq!(child.derive(2).unwrap().address, ADDRESSES[2]);
+    }
+
+    #[test]
+    fn rejects_hardened_paths_and_bad_keys() {
+        assert!(XpubWallet::new(XPUB, "m/0'").is_err());
+        assert!(XpubWallet::new(XPUB, "m/0h").is_err());
+        assert!(XpubWallet::new("xpub123", "m/0").is_err());
+    }
+
+    #[test]
+    fn indexes_are_only_saved_once_committed() {
+        let index_path = index_file("commit");
+        let wallet = XpubWallet::new(XPUB, "m/0")
+            .unwrap()
+            .index_file(&index_path);
+
+        // a reserved index is not handed out twice, but nothing is written yet
+        let first = wallet.next_address().unwrap();
+        let second = wallet.next_address().unwrap();
+
+        assert_eq!(first.address, ADDRESSES[0]);
+        assert_eq!(second.address, ADDRESSES[1]);
+        assert!(!index_path.exists());
+
+        wallet.commit(&second.path).unwrap();
+
+        let saved: BTreeMap<String, u32> =
+            serde_json::from_str(&std::fs::read_to_string(&index_path).unwrap()).unwrap();
+        assert_eq!(saved.get(&wallet.key()), Some(&2));
+
+        // committing an older index never moves the saved one back
+        wallet.commit(&first.path).unwrap();
+        assert_eq!(wallet.load_indexes().unwrap().get(&wallet.key()), Some(&2));
+
+        assert_eq!(wallet.next_address().unwrap().address, ADDRESSES[2]);
+        assert!(wallet.commit("m/0/x").is_err());
+
+        let _ = std::fs::remove_file(index_path);
+    }
+
+    #[test]
+    fn saved_indexes_win_over_a_fresh_process() {
+        let index_path = index_file("saved");
+        let wallet = XpubWallet::new(XPUB, "m/0")
+            .unwrap()
+            .index_file(&index_path);
+
+        wallet.commit("m/0/1").unwrap();
+
+        assert_eq!(wallet.next_address().unwrap().address, ADDRESSES[2]);
+
+        let _ = std::fs::remove_file(index_path);
+    }
+}